@@ -0,0 +1,127 @@
+use crate::columnar::{Column, ColumnData, PathComponent, Slot, Stripe};
+use crate::datum::Datum;
+use crate::row_expr::{
+    AverageFunction, CountFunction, MaxFunction, MinFunction, Number, RowAggFunction, SumFunction,
+};
+use bit_vec::BitVec;
+
+/// The columnar counterpart to `RowAggFunction`, taking in a whole column of values at once
+pub trait ColumnAggFunction {
+    fn process_column(&mut self, column: &Column);
+    fn result(&self) -> Datum;
+}
+
+/// Averages the numbers in the columns it's fed, see `AverageFunction`
+#[derive(Clone, Debug, Default)]
+pub struct AverageColumnFunction(AverageFunction);
+
+impl ColumnAggFunction for AverageColumnFunction {
+    fn process_column(&mut self, column: &Column) {
+        for_each_number(column, |number| self.0.process_number(number));
+    }
+
+    fn result(&self) -> Datum {
+        self.0.result()
+    }
+}
+
+/// Sums the numbers in the columns it's fed, see `SumFunction`
+#[derive(Clone, Debug, Default)]
+pub struct SumColumnFunction(SumFunction);
+
+impl ColumnAggFunction for SumColumnFunction {
+    fn process_column(&mut self, column: &Column) {
+        for_each_number(column, |number| self.0.process_number(number));
+    }
+
+    fn result(&self) -> Datum {
+        self.0.result()
+    }
+}
+
+/// The smallest number in the columns it's fed, see `MinFunction`
+#[derive(Clone, Debug, Default)]
+pub struct MinColumnFunction(MinFunction);
+
+impl ColumnAggFunction for MinColumnFunction {
+    fn process_column(&mut self, column: &Column) {
+        for_each_number(column, |number| self.0.process_number(number));
+    }
+
+    fn result(&self) -> Datum {
+        self.0.result()
+    }
+}
+
+/// The largest number in the columns it's fed, see `MaxFunction`
+#[derive(Clone, Debug, Default)]
+pub struct MaxColumnFunction(MaxFunction);
+
+impl ColumnAggFunction for MaxColumnFunction {
+    fn process_column(&mut self, column: &Column) {
+        for_each_number(column, |number| self.0.process_number(number));
+    }
+
+    fn result(&self) -> Datum {
+        self.0.result()
+    }
+}
+
+/// Counts the non-null values in the columns it's fed, whatever their type, see
+/// `CountFunction::non_null`
+#[derive(Clone, Debug, Default)]
+pub struct CountColumnFunction(CountFunction);
+
+impl ColumnAggFunction for CountColumnFunction {
+    fn process_column(&mut self, column: &Column) {
+        let nulls = column.null_map.iter().filter(|is_null| *is_null).count();
+        self.0.add((column.null_map.len() - nulls) as u64);
+    }
+
+    fn result(&self) -> Datum {
+        self.0.result()
+    }
+}
+
+/// Averages the column at a path, the columnar counterpart to `avg_using_row_expr`.
+/// None if the column doesn't hold any numbers.
+pub fn avg_using_column_expr(stripe: &Stripe, path: &[PathComponent]) -> Option<f64> {
+    let mut agg = AverageColumnFunction::default();
+    if let Some(column) = stripe.get_column(path) {
+        agg.process_column(column);
+    }
+    agg.result().as_f64()
+}
+
+/// The values of a column that aren't null
+fn non_null<'a, T: Copy>(values: &'a [T], null_map: &'a BitVec) -> impl Iterator<Item = T> + 'a {
+    values
+        .iter()
+        .zip(null_map)
+        .filter(|(_, is_null)| !is_null)
+        .map(|(value, _)| *value)
+}
+
+/// Calls `f` with every number in a column, nulls and anything that isn't a number are skipped.
+/// Plain numeric columns are read straight from their vectors, other layouts a slot at a time.
+fn for_each_number(column: &Column, mut f: impl FnMut(Number)) {
+    let null_map = &column.null_map;
+    match &column.data {
+        ColumnData::TinyInt(vec) => non_null(vec, null_map).for_each(|i| f(Number::Int(i as i64))),
+        ColumnData::SmallInt(vec) => non_null(vec, null_map).for_each(|i| f(Number::Int(i as i64))),
+        ColumnData::Int(vec) => non_null(vec, null_map).for_each(|i| f(Number::Int(i as i64))),
+        ColumnData::BigInt(vec) => non_null(vec, null_map).for_each(|i| f(Number::Int(i))),
+        ColumnData::Float(vec) => non_null(vec, null_map).for_each(|v| f(Number::Float(v))),
+        ColumnData::Constant(_) | ColumnData::Union(_) => {
+            for idx in 0..null_map.len() {
+                if let Some(number) = match column.slot(idx) {
+                    Slot::Value(datum) => Number::from_datum(&datum),
+                    Slot::Object(_) | Slot::Array(_) => None,
+                } {
+                    f(number);
+                }
+            }
+        }
+        _ => {}
+    }
+}
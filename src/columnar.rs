@@ -1,9 +1,56 @@
+mod aggregate;
+#[cfg(feature = "arrow")]
+mod arrow;
+mod assemble;
+mod bloom;
 mod column;
-use crate::columnar::column::Column;
-pub use crate::columnar::column::ColumnData;
-use crate::datum::Datum;
-use std::collections::BTreeMap;
-use serde::{Serialize, Deserialize};
+mod column_map;
+#[cfg(feature = "hll")]
+mod hll;
+mod indexes;
+mod concat;
+mod csv;
+mod disk_writer;
+mod join;
+mod null_map;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod rename;
+mod rle;
+mod row_reader;
+mod schema;
+mod self_describing;
+mod stats;
+mod string_lengths;
+mod union_layout;
+pub use crate::columnar::aggregate::AggKind;
+#[cfg(feature = "arrow")]
+pub use crate::columnar::arrow::stripe_to_arrow;
+pub use crate::columnar::assemble::{RowIter, StripeRows};
+pub use crate::columnar::column::{
+    CastError, Column, ColumnData, OffsetError, Slot, StringEncoding,
+};
+pub use crate::columnar::concat::read_stripes;
+pub use crate::columnar::disk_writer::DiskStripeWriter;
+pub use crate::columnar::indexes::Indexes;
+#[cfg(feature = "parquet")]
+pub use crate::columnar::parquet::stripe_to_parquet;
+pub use crate::columnar::rename::{to_snake_case, KeyCollision};
+pub use crate::columnar::rle::{rle_decode, rle_encode};
+pub use crate::columnar::row_reader::SplitRowReader;
+pub use crate::columnar::schema::{infer_schema, validate_datum, FieldSchema, Schema, Violation};
+pub use crate::columnar::self_describing::{read_self_describing_header, ColumnHeader, StripeHeader};
+pub use crate::columnar::stats::{ColumnStats, Predicate};
+use crate::datum::{Datum, InternalType};
+use bit_vec::BitVec;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 
 // layout overview:
 // Explicit nulls still have a slot in the vector but shouldn't be read.
@@ -30,27 +77,192 @@ use serde::{Serialize, Deserialize};
 // foo.bar -> number{nulls=[f], vals=[5]}
 // foo.[] -> object{nulls=[t], size=[1]}
 // foo.[].bar -> number{nulls=[f], vals=[5]}
+//
+// Each level of array nesting gets its own path, so a column's depth is fixed by its path and
+// mixing scalars with differently nested arrays just produces unions at the shallower levels.
+// {foo: 1}
+// {foo: [1]}
+// {foo: [[1]]}
+// foo -> union{nulls=[f,f,f] vals=[1, array{size=1}, array{size=1}]}
+// foo.[] -> union{nulls=[f,f] vals=[1, array{size=1}]}
+// foo.[].[] -> number{nulls=[f], vals=[1]}
 
-// Ideas:
-// * Swap out offset array with a smarter data structure, can we use RLE?
+// Each column also stores the indexes of its slots, the top level row followed by the position
+// within each enclosing array. Runs counting up by one (eg the rows of a field present in every
+// row) are stored as a range rather than an index per slot.
 
 /// A path to a json node
 pub type Path = Vec<PathComponent>;
 
 /// A segment of a path to a json node.
 /// Array offsets aren't stored with the
-#[derive(Debug, Eq, PartialEq, Clone, Ord, PartialOrd, Deserialize, Serialize)]
+#[derive(Debug, Eq, PartialEq, Clone, Ord, PartialOrd, Hash, Deserialize, Serialize)]
 pub enum PathComponent {
     Key(String),
     Array,
 }
 
+/// Renders a path in the dotted form used in the layout notes above, eg `foo.[].bar`.
+/// Within keys `\\`, `.`, `[` and `"` are escaped with a backslash and an empty key is written
+/// as `""`, so the result can always be parsed back with `parse_path`.
+pub fn path_to_string(path: &[PathComponent]) -> String {
+    let mut out = String::new();
+    for (idx, component) in path.iter().enumerate() {
+        if idx > 0 {
+            out.push('.');
+        }
+        match component {
+            PathComponent::Array => out.push_str("[]"),
+            PathComponent::Key(key) if key.is_empty() => out.push_str("\"\""),
+            PathComponent::Key(key) => {
+                for c in key.chars() {
+                    if matches!(c, '\\' | '.' | '[' | '"') {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Number of index levels a column at this path has, the top level row plus one per array
+fn path_depth(path: &[PathComponent]) -> usize {
+    1 + path.iter().filter(|c| **c == PathComponent::Array).count()
+}
+
+/// Parses a path in the form produced by `path_to_string`
+pub fn parse_path(s: &str) -> Result<Path, ParsePathError> {
+    let mut path = Vec::new();
+    if s.is_empty() {
+        return Ok(path);
+    }
+    let mut chars = s.chars();
+    loop {
+        // Read up to the next unescaped dot, remembering the raw text to spot the special forms
+        let mut raw = String::new();
+        let mut key = String::new();
+        let mut unescaped = false;
+        let mut at_end = true;
+        while let Some(c) = chars.next() {
+            match c {
+                '.' => {
+                    at_end = false;
+                    break;
+                }
+                '\\' => match chars.next() {
+                    Some(escaped @ ('\\' | '.' | '[' | '"')) => {
+                        raw.push(c);
+                        raw.push(escaped);
+                        key.push(escaped);
+                    }
+                    _ => return Err(ParsePathError(format!("invalid escape in {s:?}"))),
+                },
+                c => {
+                    unescaped |= matches!(c, '[' | '"');
+                    raw.push(c);
+                    key.push(c);
+                }
+            }
+        }
+        path.push(match raw.as_str() {
+            "" => return Err(ParsePathError(format!("empty path segment in {s:?}"))),
+            "[]" => PathComponent::Array,
+            "\"\"" => PathComponent::Key(String::new()),
+            _ if unescaped => {
+                return Err(ParsePathError(format!("unescaped character in {s:?}")))
+            }
+            _ => PathComponent::Key(key),
+        });
+        if at_end {
+            return Ok(path);
+        }
+    }
+}
+
+/// Error returned when a string isn't a valid path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePathError(String);
+
+impl Display for ParsePathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ParsePathError {}
+
+/// Error returned when pushing a row would take a stripe past its `max_columns`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TooManyColumns {
+    pub max_columns: usize,
+}
+
+impl Display for TooManyColumns {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "row would exceed the stripe's limit of {} columns", self.max_columns)
+    }
+}
+
+impl Error for TooManyColumns {}
+
 /// A chunk of data that's been serialized in one go.
 /// Indexes within the data are all stripe local,
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug)]
 pub struct Stripe {
     columns: BTreeMap<Path, Column>,
     count: usize,
+    row_ids: bool,
+    string_encoding: StringEncoding,
+    max_columns: Option<usize>,
+}
+
+/// How a stripe is serialized, generic over the columns so `DiskStripeWriter` can stream them in
+/// from its spill files while sharing the rest of the layout.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Stripe")]
+struct StripeLayout<C> {
+    columns: C,
+    count: usize,
+    row_ids: bool,
+    string_encoding: StringEncoding,
+    max_columns: Option<usize>,
+}
+
+impl Serialize for Stripe {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StripeLayout {
+            columns: column_map::ColumnsRef(&self.columns),
+            count: self.count,
+            row_ids: self.row_ids,
+            string_encoding: self.string_encoding,
+            max_columns: self.max_columns,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Stripe {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let layout = StripeLayout::<column_map::Columns>::deserialize(deserializer)?;
+        Ok(Stripe {
+            columns: layout.columns.0,
+            count: layout.count,
+            row_ids: layout.row_ids,
+            string_encoding: layout.string_encoding,
+            max_columns: layout.max_columns,
+        })
+    }
+}
+
+/// The key row ids are recorded under when enabled with `Stripe::with_row_ids`
+pub const ROW_ID_KEY: &str = "__rowid";
+
+impl Default for Stripe {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Stripe {
@@ -59,30 +271,303 @@ impl Stripe {
         Stripe {
             columns: BTreeMap::new(),
             count: 0,
+            row_ids: false,
+            string_encoding: StringEncoding::default(),
+            max_columns: None,
         }
     }
 
-    /// Push a datum into the stripe
+    /// Sets how string columns will be laid out
+    pub fn with_string_encoding(mut self, string_encoding: StringEncoding) -> Self {
+        self.string_encoding = string_encoding;
+        self
+    }
+
+    /// Records the ingest position of each object row under `ROW_ID_KEY`, so results can still be
+    /// traced back to their source row after joins and other transformations.
+    /// Rows that aren't objects, or that already contain the key, are left untouched.
+    pub fn with_row_ids(mut self) -> Self {
+        self.row_ids = true;
+        self
+    }
+
+    /// Caps the number of columns the stripe can hold, guarding against very heterogeneous input
+    /// (eg objects keyed by unique ids) blowing up the schema.
+    /// The limit is only checked by `try_push_datum` and `try_push_data`, the other push methods
+    /// always take the row.
+    pub fn with_max_columns(mut self, max_columns: usize) -> Self {
+        self.max_columns = Some(max_columns);
+        self
+    }
+
+    /// Push a datum into the stripe, regardless of any `max_columns` limit
     pub fn push_datum(&mut self, datum: &Datum) {
         self.push_datum_at_path(datum, &[], &[self.count]);
+        if self.row_ids {
+            if let Datum::Object(obj) = datum {
+                if !obj.contains_key(ROW_ID_KEY) {
+                    self.push_row_id();
+                }
+            }
+        }
         self.count += 1;
     }
 
+    /// Push a json value into the stripe, converting it as `Datum::from` does.
+    pub fn push_value(&mut self, value: Value) {
+        self.push_datum(&Datum::from(value));
+    }
+
+    /// Push a batch of datums into the stripe, regardless of any `max_columns` limit.
+    /// Space is reserved in the columns up front, based on how many values each has taken per row
+    /// so far, saving reallocations while pushing. Columns of scalars are never given more space
+    /// than there are scalars in the batch. When the stripe starts out empty the batch's first row
+    /// is pushed before reserving, so its columns are sized from that row. Paths that first show up
+    /// later in the batch still grow as they're pushed to.
+    pub fn push_data(&mut self, data: &[Datum]) {
+        let pushed = self.push_batch(data, |stripe, datum| {
+            stripe.push_datum(datum);
+            Ok::<_, Infallible>(())
+        });
+        match pushed {
+            Ok(()) => {}
+            Err(never) => match never {},
+        }
+    }
+
+    /// Push a batch of datums into the stripe as `push_data` does, stopping at the first datum
+    /// that would take the stripe past its `max_columns`.
+    /// The rows before that one are kept, so `len` tells how much of the batch was taken.
+    pub fn try_push_data(&mut self, data: &[Datum]) -> Result<(), TooManyColumns> {
+        self.push_batch(data, Self::try_push_datum)
+    }
+
+    /// Push a datum into the stripe, unless it would take the stripe past its `max_columns` in
+    /// which case the stripe is left untouched.
+    pub fn try_push_datum(&mut self, datum: &Datum) -> Result<(), TooManyColumns> {
+        if let Some(max_columns) = self.max_columns {
+            let mut new_paths = BTreeSet::new();
+            self.collect_new_paths(datum, &mut Vec::new(), &mut new_paths);
+            if self.row_ids {
+                new_paths.insert(vec![PathComponent::Key(ROW_ID_KEY.to_string())]);
+                new_paths.retain(|path| !self.columns.contains_key(path));
+            }
+            if self.columns.len() + new_paths.len() > max_columns {
+                return Err(TooManyColumns { max_columns });
+            }
+        }
+        self.push_datum(datum);
+        Ok(())
+    }
+
+    fn push_batch<E, F>(&mut self, data: &[Datum], mut push: F) -> Result<(), E>
+    where
+        F: FnMut(&mut Self, &Datum) -> Result<(), E>,
+    {
+        let mut data = data;
+        if self.count == 0 {
+            let Some((first, rest)) = data.split_first() else {
+                return Ok(());
+            };
+            push(self, first)?;
+            data = rest;
+        }
+        let leaves: usize = data.iter().map(|datum| datum.structure_stats().0).sum();
+        for column in self.columns.values_mut() {
+            if let Some(additional) = (column.null_map.len() * data.len()).checked_div(self.count) {
+                let additional = match column.data.type_for() {
+                    InternalType::Object | InternalType::Array | InternalType::Union => additional,
+                    _ => additional.min(leaves),
+                };
+                column.reserve(additional);
+            }
+        }
+        for datum in data {
+            push(self, datum)?;
+        }
+        Ok(())
+    }
+
+    /// Number of rows in the stripe
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Every path with a column, in sorted order so descendants come directly after their parent
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.columns.keys()
+    }
+
+    /// Every column along with its path, in the same order as `paths`
+    pub fn columns(&self) -> impl Iterator<Item = (&Path, &Column)> {
+        self.columns.iter()
+    }
+
+    /// Paths of the columns stored as the given type, in sorted order
+    pub fn columns_of_type(&self, ty: InternalType) -> Vec<&Path> {
+        self.columns
+            .iter()
+            .filter(|(_, column)| column.data.type_for() == ty)
+            .map(|(path, _)| path)
+            .collect()
+    }
+
     /// Get a column at a given path
     pub fn get_column(&self, path: &[PathComponent]) -> Option<&Column> {
         self.columns.get(path)
     }
 
+    /// Get a column using the string form of a path, see `path_to_string`
+    pub fn get_column_str(&self, path: &str) -> Option<&Column> {
+        self.get_column(&parse_path(path).ok()?)
+    }
+
+    /// Converts the column at a path to the given type, see `Column::cast_to`.
+    /// Does nothing if there's no column at the path.
+    pub fn cast_column(&mut self, path: &[PathComponent], target: InternalType) -> Result<(), CastError> {
+        match self.columns.get_mut(path) {
+            Some(column) => column.cast_to(target),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces NaN and infinite floats throughout the stripe, see `Column::canonicalize_floats`.
+    /// Worth doing before serializing if the stripe might hold non-finite floats, which only get
+    /// in when pushing datums built by hand.
+    pub fn canonicalize_floats(&mut self, sentinel: Option<f64>) {
+        for column in self.columns.values_mut() {
+            column.canonicalize_floats(sentinel);
+        }
+    }
+
+    /// Builds bloom filters over every column, letting `might_contain` rule out values
+    pub fn build_blooms(&mut self) {
+        for column in self.columns.values_mut() {
+            column.build_bloom();
+        }
+    }
+
+    /// Returns false if the value is definitely not stored at the path, allowing the stripe to be
+    /// skipped when filtering on equality. Only scalar values are tracked by the bloom filters.
+    pub fn might_contain(&self, path: &[PathComponent], value: &Datum) -> bool {
+        self.columns
+            .get(path)
+            .is_some_and(|column| column.might_contain(value))
+    }
+
+    /// Number of values (including nulls) stored at a given path.
+    /// For paths nested under arrays this counts every element, so comparing it against the
+    /// row count gives the fan-out of that path.
+    pub fn value_count(&self, path: &[PathComponent]) -> usize {
+        self.columns
+            .get(path)
+            .map_or(0, |column| column.null_map.len())
+    }
+
+    /// An owned bitmap with one bit per row, set where the value at the path is null, so masks for
+    /// several columns can be combined with `and`/`or`.
+    /// Rows where the path is missing aren't set, and for paths nested under arrays a row is set if
+    /// any of its elements are null. Returns None if there's no column at the path.
+    pub fn null_mask(&self, path: &[PathComponent]) -> Option<BitVec> {
+        self.columns
+            .get(path)
+            .map(|column| column.row_null_mask(self.count))
+    }
+
+    /// Re-encodes every column into its most compact form, call once the stripe is fully written.
+    /// Columns where every non-null value is the same collapse down to a single value, and low
+    /// cardinality string columns are dictionary encoded.
+    pub fn compact(&mut self) {
+        for column in self.columns.values_mut() {
+            column.compact();
+        }
+    }
+
+    /// Frees any spare capacity held by the columns, eg after filtering rows out or reserving
+    /// ahead with `push_data`. Worth calling before holding onto a stripe for a long time.
+    pub fn shrink_to_fit(&mut self) {
+        for column in self.columns.values_mut() {
+            column.shrink_to_fit();
+        }
+    }
+
+    /// A new empty stripe with the same options as this one
+    fn empty_copy(&self) -> Stripe {
+        Stripe {
+            row_ids: self.row_ids,
+            string_encoding: self.string_encoding,
+            max_columns: self.max_columns,
+            ..Stripe::new()
+        }
+    }
+
+    /// Adds the row id to the object just pushed as the current row
+    fn push_row_id(&mut self) {
+        let row = self.count;
+        self.column_entry(&[PathComponent::Key(ROW_ID_KEY.to_string())])
+            .add_datum(&Datum::BigInt(row as i64), &[row]);
+        let root = self.columns.get_mut(&[][..]).unwrap();
+        root.grow_object(root.null_map.len() - 1, 1);
+    }
+
+    /// A hash of every path in the stripe along with its type, stripes with the same shape share
+    /// a fingerprint so compatibility can be checked without comparing schemas.
+    /// The hash is only stable for a given build of this crate, so shouldn't be persisted.
+    pub fn schema_fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for (path, column) in &self.columns {
+            path.hash(&mut hasher);
+            column.data.type_for().hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Get a column at a given path for writing, creating it if needed
+    fn column_entry(&mut self, path: &[PathComponent]) -> &mut Column {
+        if !self.columns.contains_key(path) {
+            self.columns
+                .insert(path.to_vec(), Column::new(path_depth(path), self.string_encoding));
+        }
+        self.columns.get_mut(path).unwrap()
+    }
+
+    /// Collects the paths within a datum that don't have a column yet
+    fn collect_new_paths(&self, datum: &Datum, path: &mut Path, new_paths: &mut BTreeSet<Path>) {
+        if datum.is_missing() {
+            return;
+        }
+        if !self.columns.contains_key(path.as_slice()) {
+            new_paths.insert(path.clone());
+        }
+        match datum {
+            Datum::Object(obj) => {
+                for (key, value) in obj.iter() {
+                    path.push(PathComponent::Key(key.clone()));
+                    self.collect_new_paths(value, path, new_paths);
+                    path.pop();
+                }
+            }
+            Datum::Array(arr) => {
+                path.push(PathComponent::Array);
+                for datum in arr {
+                    self.collect_new_paths(datum, path, new_paths);
+                }
+                path.pop();
+            }
+            _ => {}
+        }
+    }
+
     /// Write datum into a given column, will recursively write nested values
     fn push_datum_at_path(&mut self, datum: &Datum, path: &[PathComponent], indexes: &[usize]) {
         if datum.is_missing() {
             return;
         }
-        if !self.columns.contains_key(path) {
-            self.columns
-                .insert(path.to_vec(), Column::new(indexes.len()));
-        }
-        let column = self.columns.get_mut(path).unwrap();
+        let column = self.column_entry(path);
         column.add_datum(datum, indexes);
 
         match datum {
@@ -111,3 +596,311 @@ impl Stripe {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::columnar::column::Slot;
+    use crate::columnar::{
+        parse_path, path_to_string, Path, PathComponent, Stripe, TooManyColumns,
+    };
+    use crate::datum::{Datum, InternalType};
+    use bit_vec::BitVec;
+    use serde_json::json;
+
+    #[test]
+    fn push_data_sizes_the_columns_of_an_empty_stripe() {
+        let rows: Vec<Datum> = (0..100)
+            .map(|i| Datum::from(json!({"id": i, "tags": ["a", "b"]})))
+            .collect();
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        for (path, len) in [("id", 100), ("tags", 100), ("tags.[]", 200)] {
+            let column = stripe.get_column(&parse_path(path).unwrap()).unwrap();
+            assert_eq!(column.null_map.len(), len);
+            // Growing by doubling as values are pushed would have left spare capacity
+            assert_eq!(column.capacity(), len, "{path}");
+        }
+    }
+
+    #[test]
+    fn empty_objects_and_arrays_round_trip() {
+        for value in [json!({"a": {}}), json!({"a": []}), json!({"a": [[]]})] {
+            let row = Datum::from(value);
+            let mut stripe = Stripe::new();
+            stripe.push_datum(&row);
+            stripe.push_datum(&Datum::from(json!({})));
+            assert_eq!(stripe.to_data(), vec![row, Datum::from(json!({}))]);
+        }
+    }
+
+    #[test]
+    fn paths_round_trip_through_strings() {
+        // Keys are built from characters that clash with the path syntax
+        const CHARS: [char; 8] = ['a', '.', '[', ']', '"', '\\', ' ', 'é'];
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = |bound: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound) as usize
+        };
+        for _ in 0..10_000 {
+            let path: Path = (0..next(6))
+                .map(|_| match next(4) {
+                    0 => PathComponent::Array,
+                    _ => PathComponent::Key((0..next(5)).map(|_| CHARS[next(8)]).collect()),
+                })
+                .collect();
+            let s = path_to_string(&path);
+            assert_eq!(parse_path(&s).as_ref(), Ok(&path), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn many_unique_keys_hit_max_columns() {
+        let mut stripe = Stripe::new().with_max_columns(10);
+        let mut accepted = Vec::new();
+        let mut rejected = None;
+        for i in 0..100 {
+            let row = Datum::from(json!({"by_id": {format!("id{i}"): i}}));
+            match stripe.try_push_datum(&row) {
+                Ok(()) => accepted.push(row),
+                Err(err) => {
+                    rejected = Some(err);
+                    break;
+                }
+            }
+        }
+        assert_eq!(rejected, Some(TooManyColumns { max_columns: 10 }));
+        // The root and `by_id` columns plus one per key
+        assert_eq!(accepted.len(), 8);
+        assert_eq!(stripe.columns().count(), 10);
+        // The rejected row left the stripe untouched
+        assert_eq!(stripe.len(), 8);
+        assert_eq!(stripe.to_data(), accepted);
+    }
+
+    #[test]
+    fn inconsistent_array_nesting_is_a_union_per_depth() {
+        let rows = [json!({"x": 1}), json!({"x": [1]}), json!({"x": [[1]]})].map(Datum::from);
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        let slots = |path: &str| {
+            let column = stripe.get_column_str(path).unwrap();
+            (0..column.null_map.len())
+                .map(|idx| column.slot(idx))
+                .collect::<Vec<_>>()
+        };
+        let one = Slot::Value(Datum::BigInt(1));
+        assert_eq!(slots("x"), [one.clone(), Slot::Array(1), Slot::Array(1)]);
+        assert_eq!(slots("x.[]"), [one, Slot::Array(1)]);
+        assert_eq!(slots("x.[].[]"), [Slot::Value(Datum::TinyInt(1))]);
+
+        // Every row reconstructs, with the ints stored in unions read back as i64s
+        let row = |x: Datum| Datum::Object([("x".to_string(), x)].into_iter().collect());
+        let expected = [
+            row(Datum::BigInt(1)),
+            row(Datum::Array(vec![Datum::BigInt(1)])),
+            row(Datum::Array(vec![Datum::Array(vec![Datum::TinyInt(1)])])),
+        ];
+        assert_eq!(stripe.to_data(), expected);
+    }
+
+    #[test]
+    fn canonicalized_floats_round_trip_through_json() {
+        // `u` starts out as a string so its floats are stored in a union
+        let row = |f: Datum, u: Datum| {
+            Datum::Object([("f".to_string(), f), ("u".to_string(), u)].into())
+        };
+        let rows = [
+            row(Datum::Float(1.5), Datum::String("s".to_string())),
+            row(Datum::Float(f64::NAN), Datum::Float(f64::NAN)),
+            row(Datum::Float(f64::INFINITY), Datum::BigInt(1)),
+            row(Datum::Float(f64::NEG_INFINITY), Datum::Float(f64::INFINITY)),
+        ];
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        // Json has no way to write non-finite floats, they come out as nulls that don't read back
+        let json = serde_json::to_string(&stripe).unwrap();
+        assert!(serde_json::from_str::<Stripe>(&json).is_err());
+
+        for (sentinel, replaced) in [(None, Datum::Null), (Some(-1.0), Datum::Float(-1.0))] {
+            let mut stripe = Stripe::new();
+            stripe.push_data(&rows);
+            stripe.canonicalize_floats(sentinel);
+            let json = serde_json::to_string(&stripe).unwrap();
+            let read: Stripe = serde_json::from_str(&json).unwrap();
+            assert_eq!(serde_json::to_string(&read).unwrap(), json);
+            assert_eq!(
+                read.to_data(),
+                [
+                    row(Datum::Float(1.5), Datum::String("s".to_string())),
+                    row(replaced.clone(), replaced.clone()),
+                    row(replaced.clone(), Datum::BigInt(1)),
+                    row(replaced.clone(), replaced.clone()),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn shrink_to_fit_frees_spare_capacity() {
+        let rows: Vec<Datum> = (0..1000)
+            .map(|i| Datum::from(json!({"id": i, "name": format!("name {i}"), "tags": ["a"]})))
+            .collect();
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        // Filtering pushes the kept rows one by one, leaving the columns room to grow
+        let mut filtered = stripe.apply_mask(&BitVec::from_fn(1000, |row| row % 10 == 0));
+        assert_eq!(filtered.columns().count(), 5);
+        for (path, column) in filtered.columns() {
+            assert!(column.capacity() > column.null_map.len(), "{}", path_to_string(path));
+        }
+        filtered.shrink_to_fit();
+        for (path, column) in filtered.columns() {
+            assert_eq!(column.capacity(), column.null_map.len(), "{}", path_to_string(path));
+        }
+        let kept: Vec<Datum> = stripe.iter_rows().step_by(10).collect();
+        assert_eq!(filtered.to_data(), kept);
+    }
+
+    #[test]
+    fn value_count_counts_every_slot() {
+        let rows: Vec<Datum> = (0..10)
+            .map(|i| {
+                let name = if i % 2 == 0 { json!(format!("n{i}")) } else { json!(null) };
+                Datum::from(json!({"id": i, "name": name, "tags": ["a", "b"], "meta": {"x": i}}))
+            })
+            .collect();
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        let count = |path: &str| stripe.value_count(&parse_path(path).unwrap());
+        // Nulls are counted as values
+        assert_eq!(count("id") + count("name") + count("tags"), 30);
+        assert_eq!(count("name"), 10);
+        // Each element of an array is a value of its own
+        assert_eq!(count("tags.[]"), 20);
+        assert_eq!(count("meta.x"), 10);
+        assert_eq!(count("missing"), 0);
+    }
+
+    #[test]
+    fn schema_fingerprints_follow_the_shape() {
+        let fingerprint = |rows: serde_json::Value| {
+            let mut stripe = Stripe::new();
+            for row in rows.as_array().unwrap() {
+                stripe.push_value(row.clone());
+            }
+            stripe.schema_fingerprint()
+        };
+        let base = fingerprint(json!([{"a": 1, "b": ["x"]}, {"a": 2}]));
+        // Same paths and types, different values and rows
+        assert_eq!(base, fingerprint(json!([{"b": ["y", "z"], "a": 100}])));
+        // A different type at a path
+        assert_ne!(base, fingerprint(json!([{"a": 1.5, "b": ["x"]}])));
+        assert_ne!(base, fingerprint(json!([{"a": 1000, "b": ["x"]}])));
+        // A different path
+        assert_ne!(base, fingerprint(json!([{"a": 1, "c": ["x"]}])));
+        assert_ne!(base, fingerprint(json!([{"a": 1, "b": "x"}])));
+    }
+
+    #[test]
+    fn might_contain_rules_out_absent_values() {
+        let rows: Vec<Datum> = (0..20)
+            .map(|i| Datum::from(json!({"user": format!("user{i}"), "n": i, "tags": ["t"]})))
+            .collect();
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        let user = parse_path("user").unwrap();
+        let absent = Datum::String("nobody".to_string());
+        // Without bloom filters nothing can be ruled out
+        assert!(stripe.might_contain(&user, &absent));
+
+        stripe.build_blooms();
+        assert!(stripe.might_contain(&user, &Datum::String("user7".to_string())));
+        assert!(!stripe.might_contain(&user, &absent));
+        assert!(stripe.might_contain(&parse_path("n").unwrap(), &Datum::TinyInt(19)));
+        assert!(!stripe.might_contain(&parse_path("n").unwrap(), &Datum::TinyInt(100)));
+        assert!(stripe.might_contain(&parse_path("tags.[]").unwrap(), &Datum::String("t".into())));
+        // No nulls were stored, and nothing is stored at a missing path
+        assert!(!stripe.might_contain(&user, &Datum::Null));
+        assert!(!stripe.might_contain(&parse_path("missing").unwrap(), &absent));
+    }
+
+    #[test]
+    fn max_columns_only_limits_the_try_push_methods() {
+        let rows: Vec<Datum> = (0..20).map(|i| Datum::from(json!({format!("id{i}"): i}))).collect();
+
+        let mut stripe = Stripe::new().with_max_columns(5);
+        stripe.push_data(&rows);
+        stripe.push_datum(&Datum::from(json!({"extra": 1})));
+        assert_eq!(stripe.len(), 21);
+        assert_eq!(stripe.columns().count(), 22);
+
+        let mut stripe = Stripe::new().with_max_columns(5);
+        let rejected = stripe.try_push_data(&rows);
+        assert_eq!(rejected, Err(TooManyColumns { max_columns: 5 }));
+        // The root column plus one per key
+        assert_eq!(stripe.len(), 4);
+        assert_eq!(stripe.to_data(), rows[..4]);
+    }
+
+    #[test]
+    fn null_masks_combine_across_columns() {
+        let mut stripe = Stripe::new();
+        for row in [
+            json!({"a": null, "b": null, "l": [1, null]}),
+            json!({"a": null, "b": 1, "l": [2]}),
+            json!({"a": 1, "b": null}),
+            json!({"b": null}),
+        ] {
+            stripe.push_value(row);
+        }
+        let mask = |path| stripe.null_mask(&parse_path(path).unwrap()).unwrap();
+        let mut both = mask("a");
+        assert_eq!(both, BitVec::from_fn(4, |row| row < 2));
+        both.and(&mask("b"));
+        assert_eq!(both, BitVec::from_fn(4, |row| row == 0));
+        // A row is set if any of its elements are null
+        assert_eq!(mask("l.[]"), BitVec::from_fn(4, |row| row == 0));
+        assert_eq!(stripe.null_mask(&parse_path("c").unwrap()), None);
+    }
+
+    #[test]
+    fn columns_of_type_picks_out_numeric_paths() {
+        let mut stripe = Stripe::new();
+        stripe.push_value(json!({"a": 1, "b": 1.5, "c": "s", "d": {"e": 2, "f": [3]}, "g": 1}));
+        stripe.push_value(json!({"a": 300, "g": "mixed"}));
+        let paths = |ty| -> Vec<String> {
+            stripe.columns_of_type(ty).into_iter().map(|path| path_to_string(path)).collect()
+        };
+        assert_eq!(paths(InternalType::TinyInt), ["d.e", "d.f.[]"]);
+        assert_eq!(paths(InternalType::SmallInt), ["a"]);
+        assert_eq!(paths(InternalType::Float), ["b"]);
+        assert_eq!(paths(InternalType::Union), ["g"]);
+        assert!(paths(InternalType::BigInt).is_empty());
+    }
+
+    #[test]
+    fn stripes_round_trip_through_bincode() {
+        let rows: Vec<Datum> = [
+            json!({"user": {"name": "ann", "address": {"city": "x"}}, "tags": [["a"], []], "v": 1}),
+            json!({"user": {"name": null}, "tags": [], "v": "one"}),
+            json!({"v": [1.5, {"w": true}]}),
+            json!(null),
+        ]
+        .into_iter()
+        .map(Datum::from)
+        .collect();
+        for mut stripe in [Stripe::new(), Stripe::new().with_row_ids().with_max_columns(20)] {
+            stripe.push_data(&rows);
+            let bytes = bincode::serialize(&stripe).unwrap();
+            let read_back: Stripe = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(read_back.to_data(), stripe.to_data());
+            assert_eq!(bincode::serialize(&read_back).unwrap(), bytes);
+            // `v` and its elements mix types so are stored as unions
+            let unions = [parse_path("v").unwrap(), parse_path("v.[]").unwrap()];
+            assert_eq!(read_back.columns_of_type(InternalType::Union), unions.each_ref());
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::column_expr::{
+    AverageColumnFunction, ColumnAggFunction, CountColumnFunction, MaxColumnFunction,
+    MinColumnFunction, SumColumnFunction,
+};
+use crate::columnar::{path_to_string, PathComponent, Stripe};
+use crate::datum::Datum;
+use std::collections::HashMap;
+
+/// The aggregations that can be run over a column with `Stripe::aggregate`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AggKind {
+    /// Number of non-null values
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggKind {
+    /// Name used as the prefix of the aggregate's output key
+    pub fn name(&self) -> &'static str {
+        match self {
+            AggKind::Count => "count",
+            AggKind::Sum => "sum",
+            AggKind::Avg => "avg",
+            AggKind::Min => "min",
+            AggKind::Max => "max",
+        }
+    }
+
+    /// A fresh instance of the columnar aggregate that computes this
+    pub fn column_function(&self) -> Box<dyn ColumnAggFunction> {
+        match self {
+            AggKind::Count => Box::new(CountColumnFunction::default()),
+            AggKind::Sum => Box::new(SumColumnFunction::default()),
+            AggKind::Avg => Box::new(AverageColumnFunction::default()),
+            AggKind::Min => Box::new(MinColumnFunction::default()),
+            AggKind::Max => Box::new(MaxColumnFunction::default()),
+        }
+    }
+}
+
+impl Stripe {
+    /// Runs several aggregates over the stripe in one call, returning an object keyed by
+    /// `{kind}_{path}`, eg `avg_payload.size`.
+    /// Every value stored at the path is aggregated, including each element for paths nested under
+    /// arrays. Nulls are skipped, as are non-numeric values for everything except `Count`, and
+    /// aggregates other than `Count` with no values to work with come out as null.
+    /// Each aggregate is run with its `column_function`, so results follow the same rules as
+    /// `RowAggFunction`s: `Count` is a `BigInt`, and `Sum`, `Min` and `Max` stay integers unless
+    /// they meet a float (or the sum overflows).
+    pub fn aggregate(&self, spec: &[(Vec<PathComponent>, AggKind)]) -> Datum {
+        let results = spec
+            .iter()
+            .map(|(path, kind)| {
+                let name = format!("{}_{}", kind.name(), path_to_string(path));
+                (name, self.aggregate_column(path, *kind))
+            })
+            .collect::<HashMap<_, _>>();
+        Datum::Object(results)
+    }
+
+    fn aggregate_column(&self, path: &[PathComponent], kind: AggKind) -> Datum {
+        let mut agg = kind.column_function();
+        if let Some(column) = self.columns.get(path) {
+            agg.process_column(column);
+        }
+        agg.result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::columnar::{parse_path, AggKind, Stripe};
+    use crate::datum::Datum;
+    use serde_json::json;
+
+    #[test]
+    fn avg_and_sum_in_one_call() {
+        let mut stripe = Stripe::new();
+        for row in [
+            json!({"x": 1, "y": 10}),
+            json!({"x": 2.5, "y": 20}),
+            json!({"x": null, "y": 9007199254740993i64}),
+            json!({"x": 4.5}),
+        ] {
+            stripe.push_value(row);
+        }
+
+        let result = stripe.aggregate(&[
+            (parse_path("x").unwrap(), AggKind::Avg),
+            (parse_path("y").unwrap(), AggKind::Sum),
+            (parse_path("x").unwrap(), AggKind::Count),
+            (parse_path("z").unwrap(), AggKind::Sum),
+            (parse_path("z").unwrap(), AggKind::Count),
+        ]);
+        let expected = [
+            ("avg_x", Datum::Float(8.0 / 3.0)),
+            ("sum_y", Datum::BigInt(9007199254741023)),
+            ("count_x", Datum::BigInt(3)),
+            ("sum_z", Datum::Null),
+            ("count_z", Datum::BigInt(0)),
+        ];
+        assert_eq!(
+            result,
+            Datum::Object(expected.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+        );
+    }
+}
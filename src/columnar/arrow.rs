@@ -0,0 +1,72 @@
+use crate::columnar::column::Column;
+use crate::columnar::{path_depth, path_to_string, Slot, Stripe};
+use crate::datum::{Datum, InternalType};
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, NullArray,
+    RecordBatch, RecordBatchOptions, StringArray,
+};
+use arrow_schema::{Field, Schema};
+use std::sync::Arc;
+
+/// Converts a stripe into an arrow `RecordBatch` with a field per scalar column, named by its
+/// dotted path (see `path_to_string`), so nested objects are flattened out.
+/// Rows where the path is null or missing are null in arrow.
+/// Columns nested under arrays don't have a value per row so are left out for now, as are union
+/// columns.
+pub fn stripe_to_arrow(stripe: &Stripe) -> RecordBatch {
+    let mut fields = Vec::new();
+    let mut arrays: Vec<ArrayRef> = Vec::new();
+    for (path, column) in &stripe.columns {
+        if path_depth(path) > 1 {
+            continue;
+        }
+        let rows = row_values(column, stripe.count);
+        let array: ArrayRef = match column.data.type_for() {
+            InternalType::Null | InternalType::Missing => Arc::new(NullArray::new(stripe.count)),
+            InternalType::Bool => {
+                Arc::new(BooleanArray::from_iter(rows.map(|datum| datum?.as_bool())))
+            }
+            InternalType::TinyInt => Arc::new(Int8Array::from_iter(
+                rows.map(|datum| Some(datum?.as_i64()? as i8)),
+            )),
+            InternalType::SmallInt => Arc::new(Int16Array::from_iter(
+                rows.map(|datum| Some(datum?.as_i64()? as i16)),
+            )),
+            InternalType::Int => Arc::new(Int32Array::from_iter(
+                rows.map(|datum| Some(datum?.as_i64()? as i32)),
+            )),
+            InternalType::BigInt => {
+                Arc::new(Int64Array::from_iter(rows.map(|datum| datum?.as_i64())))
+            }
+            InternalType::Float => {
+                Arc::new(Float64Array::from_iter(rows.map(|datum| datum?.as_f64())))
+            }
+            InternalType::String => Arc::new(StringArray::from_iter(
+                rows.map(|datum| datum?.as_str().map(str::to_string)),
+            )),
+            InternalType::Object | InternalType::Array | InternalType::Union => continue,
+        };
+        fields.push(Field::new(
+            path_to_string(path),
+            array.data_type().clone(),
+            true,
+        ));
+        arrays.push(array);
+    }
+    let options = RecordBatchOptions::new().with_row_count(Some(stripe.count));
+    RecordBatch::try_new_with_options(Arc::new(Schema::new(fields)), arrays, &options)
+        .expect("every array has a value per row")
+}
+
+/// The value of a top level column for each row, None where it's null or missing
+fn row_values(column: &Column, rows: usize) -> impl Iterator<Item = Option<Datum>> + '_ {
+    let mut slots = column.row_indexes().iter().enumerate().peekable();
+    (0..rows).map(move |row| {
+        let (idx, _) = slots.next_if(|(_, slot_row)| *slot_row as usize == row)?;
+        match column.slot(idx) {
+            Slot::Value(Datum::Null) => None,
+            Slot::Value(datum) => Some(datum),
+            Slot::Object(_) | Slot::Array(_) => None,
+        }
+    })
+}
@@ -0,0 +1,327 @@
+use crate::columnar::column::Slot;
+use crate::columnar::{path_depth, path_to_string, Path, PathComponent, Stripe};
+use crate::datum::Datum;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io;
+use std::ops::{Bound, Range};
+
+impl Stripe {
+    /// Reassembles all the rows in the stripe back into datums
+    pub fn to_data(&self) -> Vec<Datum> {
+        self.iter_rows().collect()
+    }
+
+    /// Lazily reassembles each row in the stripe in turn
+    pub fn iter_rows(&self) -> RowIter<'_> {
+        RowIter {
+            stripe: self,
+            rows: 0..self.count,
+        }
+    }
+
+    /// A view over the rows of the stripe, for treating it like a `Vec<Datum>`.
+    /// Rows are reassembled on demand.
+    pub fn rows(&self) -> StripeRows<'_> {
+        StripeRows { stripe: self }
+    }
+
+    /// Reassembles a single row, returns `Datum::Missing` if the row is out of range
+    pub fn get_row(&self, row: usize) -> Datum {
+        self.assemble(&mut Vec::new(), &mut vec![row])
+    }
+
+    /// Builds a new stripe by reassembling each row and passing it through `f`.
+    /// Much slower than working on the columns directly, but can express any transformation.
+    pub fn map_rows(&self, f: impl Fn(Datum) -> Datum) -> Stripe {
+        let mut stripe = self.empty_copy();
+        for row in self.iter_rows() {
+            stripe.push_datum(&f(row));
+        }
+        stripe
+    }
+
+    /// Writes each row out as a line of compact json, the inverse of loading a newline delimited
+    /// json file. Explicit nulls are written while missing keys are left out.
+    pub fn write_ndjson<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        for row in self.iter_rows() {
+            serde_json::to_writer(&mut writer, &Value::from(&row))?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()
+    }
+
+    /// Describes how the value at a path is reconstructed for a row, listing each column that's
+    /// read, the slots belonging to the row along with their indexes, and the raw values stored.
+    /// Intended for debugging the columnar layout.
+    pub fn explain(&self, path: &[PathComponent], row: usize) -> String {
+        let mut out = format!("{} for row {row}:\n", path_to_string(path));
+        let columns = self
+            .columns
+            .range::<[PathComponent], _>((Bound::Included(path), Bound::Unbounded))
+            .take_while(|(column_path, _)| column_path.starts_with(path));
+        let mut found = false;
+        for (column_path, column) in columns {
+            found = true;
+            writeln!(
+                out,
+                "  column {} ({:?}, {} index levels)",
+                path_to_string(column_path),
+                column.data.type_for(),
+                path_depth(column_path)
+            )
+            .unwrap();
+            let slots = column.row_range(row);
+            if slots.is_empty() {
+                writeln!(out, "    no slots for this row").unwrap();
+            }
+            for idx in slots {
+                let value = match column.slot(idx) {
+                    Slot::Value(datum) => datum.to_string(),
+                    Slot::Object(size) => format!("object with {size} keys"),
+                    Slot::Array(size) => format!("array of {size} elements"),
+                };
+                writeln!(out, "    slot {idx} at indexes {:?}: {value}", column.slot_indexes(idx))
+                    .unwrap();
+            }
+        }
+        if !found {
+            writeln!(out, "  no column at this path").unwrap();
+        }
+        out
+    }
+
+    /// Reassembles the value stored at a given path, the indexes are the top level row followed by
+    /// the position within each enclosing array.
+    /// Returns `Datum::Missing` if nothing was stored there.
+    pub(super) fn assemble(&self, path: &mut Path, indexes: &mut Vec<usize>) -> Datum {
+        let slot = self
+            .columns
+            .get(path.as_slice())
+            .and_then(|column| column.find(indexes).map(|idx| column.slot(idx)));
+
+        match slot {
+            None => Datum::Missing,
+            Some(Slot::Value(datum)) => datum,
+            // Objects are driven off the child columns, so an object with no children comes
+            // back out empty rather than missing
+            Some(Slot::Object(size)) => {
+                let mut obj = HashMap::with_capacity(size);
+                for key in self.child_keys(path) {
+                    path.push(PathComponent::Key(key));
+                    let value = self.assemble(path, indexes);
+                    if let Some(PathComponent::Key(key)) = path.pop() {
+                        if !value.is_missing() {
+                            obj.insert(key, value);
+                        }
+                    }
+                }
+                Datum::Object(obj)
+            }
+            Some(Slot::Array(size)) => {
+                path.push(PathComponent::Array);
+                let mut arr = Vec::with_capacity(size);
+                for idx in 0..size {
+                    indexes.push(idx);
+                    arr.push(self.assemble(path, indexes));
+                    indexes.pop();
+                }
+                path.pop();
+                Datum::Array(arr)
+            }
+        }
+    }
+
+    /// The keys of all the object columns directly beneath a path
+    fn child_keys(&self, path: &[PathComponent]) -> Vec<String> {
+        // Descendants sort directly after their parent
+        self.columns
+            .range::<[PathComponent], _>((Bound::Excluded(path), Bound::Unbounded))
+            .map(|(child_path, _)| child_path)
+            .take_while(|child_path| child_path.starts_with(path))
+            .filter_map(|child_path| match &child_path[path.len()..] {
+                [PathComponent::Key(key)] => Some(key.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Rows of a stripe, see `Stripe::rows`
+#[derive(Clone, Copy)]
+pub struct StripeRows<'a> {
+    stripe: &'a Stripe,
+}
+
+impl<'a> StripeRows<'a> {
+    pub fn len(&self) -> usize {
+        self.stripe.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stripe.is_empty()
+    }
+
+    /// Reassembles a single row, returns `Datum::Missing` if the row is out of range
+    pub fn get(&self, row: usize) -> Datum {
+        self.stripe.get_row(row)
+    }
+
+    pub fn iter(&self) -> RowIter<'a> {
+        self.stripe.iter_rows()
+    }
+}
+
+impl<'a> IntoIterator for StripeRows<'a> {
+    type Item = Datum;
+    type IntoIter = RowIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator reassembling the rows of a stripe, see `Stripe::iter_rows`
+pub struct RowIter<'a> {
+    stripe: &'a Stripe,
+    rows: Range<usize>,
+}
+
+impl Iterator for RowIter<'_> {
+    type Item = Datum;
+
+    fn next(&mut self) -> Option<Datum> {
+        self.rows.next().map(|row| self.stripe.get_row(row))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Datum> {
+        self.rows.nth(n).map(|row| self.stripe.get_row(row))
+    }
+}
+
+impl DoubleEndedIterator for RowIter<'_> {
+    fn next_back(&mut self) -> Option<Datum> {
+        self.rows.next_back().map(|row| self.stripe.get_row(row))
+    }
+}
+
+impl ExactSizeIterator for RowIter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::columnar::{parse_path, Stripe};
+    use crate::datum::Datum;
+    use serde_json::json;
+
+    #[test]
+    fn shredded_rows_reassemble() {
+        let rows = [
+            json!({
+                "id": 1,
+                "name": "ann",
+                "tags": ["x", "y"],
+                "loc": {"lat": 1.5, "lng": -2.5},
+                "orders": [{"sku": "s1", "qty": 2}, {"sku": "s2"}],
+            }),
+            json!({"id": 2, "name": null, "tags": [], "orders": []}),
+            json!({
+                "id": 3,
+                "tags": ["z"],
+                "loc": {"lat": 0.5, "lng": null},
+                "orders": [{"qty": 5, "sku": null}],
+                "matrix": [[1, 2], [], [3]],
+            }),
+            json!({}),
+        ]
+        .map(Datum::from);
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        for (idx, row) in rows.iter().enumerate() {
+            assert_eq!(&stripe.get_row(idx), row, "row {idx}");
+        }
+        assert_eq!(stripe.get_row(rows.len()), Datum::Missing);
+    }
+
+    #[test]
+    fn map_rows_rebuilds_the_stripe() {
+        let rows = [json!({"n": 1, "tags": ["a"]}), json!({"n": 2}), json!(null)].map(Datum::from);
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+
+        let mapped = stripe.map_rows(|row| match row {
+            Datum::Object(mut obj) => {
+                obj.remove("tags");
+                obj.insert("seen".to_string(), Datum::Bool(true));
+                Datum::Object(obj)
+            }
+            other => other,
+        });
+        let expected = [json!({"n": 1, "seen": true}), json!({"n": 2, "seen": true}), json!(null)];
+        assert_eq!(mapped.to_data(), expected.map(Datum::from));
+        assert_eq!(mapped.columns().count(), 3);
+        // The source stripe is left as it was
+        assert_eq!(stripe.to_data(), rows);
+    }
+
+    #[test]
+    fn explain_lists_the_slots_of_a_row() {
+        let mut stripe = Stripe::new();
+        for row in [json!({"a": [1, null]}), json!({"a": []}), json!({})] {
+            stripe.push_value(row);
+        }
+        let a = parse_path("a").unwrap();
+        assert_eq!(
+            stripe.explain(&a, 0),
+            concat!(
+                "a for row 0:\n",
+                "  column a (Array, 1 index levels)\n",
+                "    slot 0 at indexes [0]: array of 2 elements\n",
+                "  column a.[] (TinyInt, 2 index levels)\n",
+                "    slot 0 at indexes [0, 0]: 1\n",
+                "    slot 1 at indexes [0, 1]: null\n",
+            )
+        );
+        assert_eq!(
+            stripe.explain(&a, 2),
+            concat!(
+                "a for row 2:\n",
+                "  column a (Array, 1 index levels)\n",
+                "    no slots for this row\n",
+                "  column a.[] (TinyInt, 2 index levels)\n",
+                "    no slots for this row\n",
+            )
+        );
+        assert_eq!(
+            stripe.explain(&parse_path("b").unwrap(), 0),
+            "b for row 0:\n  no column at this path\n"
+        );
+    }
+
+    #[test]
+    fn rows_act_like_a_vec() {
+        let data = [json!({"n": 1}), json!([2]), json!(null), json!({"m": {"k": 3}})];
+        let data = data.map(Datum::from);
+        let mut stripe = Stripe::new();
+        stripe.push_data(&data);
+        let rows = stripe.rows();
+        assert_eq!((rows.len(), rows.is_empty()), (4, false));
+        assert_eq!(rows.get(1), data[1]);
+        assert_eq!(rows.get(4), Datum::Missing);
+        assert_eq!(rows.into_iter().collect::<Vec<_>>(), data);
+
+        let mut iter = rows.iter();
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.next_back(), Some(data[3].clone()));
+        assert_eq!(iter.next(), Some(data[0].clone()));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.nth(1), Some(data[2].clone()));
+        assert_eq!((iter.next(), iter.next_back()), (None, None));
+        assert!(rows.iter().rev().eq(data.iter().rev().cloned()));
+        assert!(Stripe::new().rows().is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+use crate::datum::Datum;
+use bit_vec::BitVec;
+use serde::{Deserialize, Serialize};
+
+/// Bits allocated per value, along with the number of hashes this gives roughly a 1% false
+/// positive rate while the filter holds no more values than it was sized for.
+const BITS_PER_VALUE: usize = 10;
+const HASHES: u64 = 7;
+
+/// A bloom filter over the scalar values in a column, used to prove a value is absent.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BloomFilter {
+    bits: BitVec,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized for the expected number of values
+    pub fn with_capacity(values: usize) -> Self {
+        BloomFilter {
+            bits: BitVec::from_elem((values * BITS_PER_VALUE).max(64), false),
+        }
+    }
+
+    /// Records a value, nested values and nulls aren't tracked
+    pub fn insert(&mut self, datum: &Datum) {
+        if let Some(hash) = hash_scalar(datum) {
+            for bit in self.bit_positions(hash) {
+                self.bits.set(bit, true);
+            }
+        }
+    }
+
+    /// Returns false if the value definitely hasn't been inserted
+    pub fn might_contain(&self, datum: &Datum) -> bool {
+        match hash_scalar(datum) {
+            Some(hash) => self.bit_positions(hash).all(|bit| self.bits[bit]),
+            None => true,
+        }
+    }
+
+    fn bit_positions(&self, hash: u64) -> impl Iterator<Item = usize> {
+        // Double hashing, deriving all our hashes from the two halves of one
+        let (h1, h2) = (hash & 0xffff_ffff, hash >> 32);
+        let len = self.bits.len() as u64;
+        (0..HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+}
+
+/// A hash of a scalar value that's stable across builds (as the filter gets persisted) and
+/// treats numbers the same regardless of their internal width.
+pub(super) fn hash_scalar(datum: &Datum) -> Option<u64> {
+    let (tag, bytes) = match datum {
+        Datum::Bool(b) => (0, vec![*b as u8]),
+        Datum::String(_) | Datum::SharedString(_) => (1, datum.as_str()?.as_bytes().to_vec()),
+        // Normalize -0.0 so it matches 0.0
+        _ => (2, (datum.as_f64()? + 0.0).to_bits().to_le_bytes().to_vec()),
+    };
+    // FNV-1a
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in std::iter::once(tag).chain(bytes) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    Some(hash)
+}
@@ -1,76 +1,591 @@
-use crate::datum::{Datum, InternalType};
+use crate::columnar::bloom::BloomFilter;
+#[cfg(feature = "hll")]
+use crate::columnar::hll::HyperLogLog;
+use crate::columnar::indexes::Indexes;
+use crate::datum::{Datum, InternalType, JsonType};
 use bit_vec::BitVec;
 use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
 
 /// Represents the data at a given path
-#[derive(Debug, Serialize, Deserialize)]
+/// `DiskStripeWriter` writes columns out field by field, so keep it in step when changing the
+/// serialized layout.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedColumn")]
 pub struct Column {
-    indexes: Vec<Vec<u32>>,
+    indexes: Vec<Indexes>,
     pub data: ColumnData,
+    #[serde(with = "crate::columnar::null_map")]
     pub null_map: BitVec,
+    string_encoding: StringEncoding,
+    bloom: Option<BloomFilter>,
+}
+
+/// A column as it comes off the wire, before `validate_offsets` has checked it's safe to read.
+#[derive(Deserialize)]
+struct UncheckedColumn {
+    indexes: Vec<Indexes>,
+    data: ColumnData,
+    #[serde(with = "crate::columnar::null_map")]
+    null_map: BitVec,
+    string_encoding: StringEncoding,
+    bloom: Option<BloomFilter>,
+}
+
+impl TryFrom<UncheckedColumn> for Column {
+    type Error = OffsetError;
+
+    fn try_from(unchecked: UncheckedColumn) -> Result<Self, Self::Error> {
+        let column = Column {
+            indexes: unchecked.indexes,
+            data: unchecked.data,
+            null_map: unchecked.null_map,
+            string_encoding: unchecked.string_encoding,
+            bloom: unchecked.bloom,
+        };
+        column.validate_offsets()?;
+        Ok(column)
+    }
 }
 
 impl Column {
-    pub(super) fn new(depth: usize) -> Self {
+    pub(super) fn new(depth: usize, string_encoding: StringEncoding) -> Self {
         Column {
-            indexes: vec![Vec::new(); depth],
+            indexes: vec![Indexes::default(); depth],
             data: ColumnData::Null,
             null_map: BitVec::new(),
+            string_encoding,
+            bloom: None,
+        }
+    }
+
+    /// An empty column of the same type
+    pub(super) fn empty_like(&self) -> Column {
+        Column {
+            indexes: vec![Indexes::default(); self.indexes.len()],
+            data: self.data.empty_like(),
+            null_map: BitVec::new(),
+            string_encoding: self.string_encoding,
+            bloom: None,
         }
     }
 
     pub(super) fn add_datum(&mut self, datum: &Datum, indexes: &[usize]) {
+        // A float column would round large integers
+        if let (ColumnData::Float(_), Datum::BigInt(i)) = (&self.data, datum) {
+            if !exact_as_float(*i) {
+                self.up_cast(InternalType::Union);
+            }
+        }
         self.up_cast(datum.internal_type());
         for (index, index_buf) in indexes.iter().zip(self.indexes.iter_mut()) {
             index_buf.push(*index as u32);
         }
         self.null_map.push(datum.is_null());
-        match (&mut self.data, datum) {
-            (ColumnData::Null, Datum::Null) => {}
-            (ColumnData::Null, _) => unreachable!(),
-            (ColumnData::Bool(vec), Datum::Bool(b)) => vec.push(*b),
-            (ColumnData::Bool(vec), Datum::Null) => vec.push(false),
-            (ColumnData::Bool(_), _) => unreachable!(),
-            (ColumnData::TinyInt(vec), Datum::TinyInt(i)) => vec.push(*i),
-            (ColumnData::TinyInt(vec), Datum::Null) => vec.push(0),
-            (ColumnData::TinyInt(_), _) => unreachable!(),
-            (ColumnData::SmallInt(vec), Datum::SmallInt(i)) => vec.push(*i),
-            (ColumnData::SmallInt(vec), Datum::TinyInt(i)) => vec.push(*i as i16),
-            (ColumnData::SmallInt(vec), Datum::Null) => vec.push(0),
-            (ColumnData::SmallInt(_), _) => unreachable!(),
-            (ColumnData::Float(vec), Datum::Float(f)) => vec.push(*f),
-            (ColumnData::Float(vec), Datum::SmallInt(i)) => vec.push(*i as f64),
-            (ColumnData::Float(vec), Datum::TinyInt(i)) => vec.push(*i as f64),
-            (ColumnData::Float(vec), Datum::Null) => vec.push(0.0),
-            (ColumnData::Float(_), _) => unreachable!(),
-            (ColumnData::String(str_buf, offsets), Datum::String(str)) => {
-                str_buf.push_str(str);
-                offsets.push(str_buf.len());
+        self.data.push_datum(datum);
+        if let Some(bloom) = &mut self.bloom {
+            bloom.insert(datum);
+        }
+    }
+
+    /// Builds a bloom filter over the values currently in the column, the filter is kept up to
+    /// date as more values are added.
+    pub(super) fn build_bloom(&mut self) {
+        let mut bloom = BloomFilter::with_capacity(self.null_map.len());
+        for idx in 0..self.null_map.len() {
+            if let Slot::Value(datum) = self.slot(idx) {
+                bloom.insert(&datum);
             }
-            (ColumnData::String(str_buf, offsets), Datum::Null) => {
-                offsets.push(str_buf.len());
+        }
+        self.bloom = Some(bloom);
+    }
+
+    pub(super) fn has_bloom(&self) -> bool {
+        self.bloom.is_some()
+    }
+
+    /// Checks the column can be read without panicking, ie every level of indexes and the data
+    /// hold an entry per slot, and the string offsets of a string column never go backwards and
+    /// land on char boundaries within the buffer. This is run automatically when deserializing.
+    pub fn validate_offsets(&self) -> Result<(), OffsetError> {
+        let slots = self.null_map.len();
+        for (level, indexes) in self.indexes.iter().enumerate() {
+            if indexes.len() != slots {
+                return Err(OffsetError::IndexCountMismatch {
+                    level,
+                    expected: slots,
+                    actual: indexes.len(),
+                });
+            }
+        }
+        self.data.validate_len(slots)?;
+        self.data.validate_offsets(slots)
+    }
+
+    /// Returns false if the value definitely isn't stored in this column.
+    /// Without a bloom filter we can only rule out nulls.
+    pub fn might_contain(&self, datum: &Datum) -> bool {
+        match (datum, &self.bloom) {
+            (Datum::Null, _) => self.null_map.any(),
+            (_, Some(bloom)) => bloom.might_contain(datum),
+            (_, None) => true,
+        }
+    }
+
+    /// The indexes of each slot, with one level per index and an entry per slot within each level.
+    /// The first level is the top level row the slot belongs to, followed by the slot's position
+    /// within each enclosing array, outermost first. So for `foo.[].[]` the slot holding `3` in
+    /// row 5's `{"foo": [[1], [2, 3]]}` has indexes 5, 1 and 1.
+    /// Slots are sorted by their indexes, use `Indexes::to_vec` to materialize a level.
+    pub fn indexes(&self) -> &[Indexes] {
+        &self.indexes
+    }
+
+    /// The top level row each slot belongs to, ie the first level of `indexes`
+    pub fn row_indexes(&self) -> &Indexes {
+        &self.indexes[0]
+    }
+
+    /// The range of slots that belong to the given top level row
+    pub(super) fn row_range(&self, row: usize) -> Range<usize> {
+        let rows = &self.indexes[0];
+        let start = rows.partition_point(|r| (r as usize) < row);
+        let end = rows.partition_point(|r| (r as usize) <= row);
+        start..end
+    }
+
+    /// The indexes (top level row followed by array positions) of a slot
+    pub(super) fn slot_indexes(&self, idx: usize) -> Vec<u32> {
+        self.indexes.iter().map(|level| level.get(idx)).collect()
+    }
+
+    /// Finds the slot stored for the given indexes (top level row followed by array positions)
+    pub(super) fn find(&self, indexes: &[usize]) -> Option<usize> {
+        let (mut low, mut high) = (0, self.null_map.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            let key = self.indexes.iter().map(|level| level.get(mid) as usize);
+            match key.cmp(indexes.iter().copied()) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Some(mid),
             }
-            (ColumnData::String(_, _), _) => unreachable!(),
-            (ColumnData::Array(sizes), Datum::Array(arr)) => sizes.push(arr.len()),
-            (ColumnData::Array(sizes), Datum::Null) => sizes.push(0),
-            (ColumnData::Array(_), _) => unreachable!(),
-            (ColumnData::Object(sizes), Datum::Object(obj)) => sizes.push(obj.len()),
-            (ColumnData::Object(sizes), Datum::Null) => sizes.push(0),
-            (ColumnData::Object(_), _) => unreachable!(),
-            (ColumnData::Union(vec), Datum::Null) => vec.push(Union::Null),
-            (ColumnData::Union(_), Datum::Missing) => unreachable!(),
-            (ColumnData::Union(vec), Datum::Bool(b)) => vec.push(Union::Bool(*b)),
-            (ColumnData::Union(vec), Datum::TinyInt(i)) => vec.push(Union::Float(*i as f64)),
-            (ColumnData::Union(vec), Datum::SmallInt(i)) => vec.push(Union::Float(*i as f64)),
-            (ColumnData::Union(vec), Datum::Float(f)) => vec.push(Union::Float(*f)),
-            (ColumnData::Union(vec), Datum::String(s)) => vec.push(Union::String(s.clone())),
-            (ColumnData::Union(vec), Datum::Object(obj)) => vec.push(Union::Object(obj.len())),
-            (ColumnData::Union(vec), Datum::Array(arr)) => vec.push(Union::Array(arr.len())),
         }
+        None
+    }
+
+    /// Estimates the number of distinct non-null scalar values in the column using a HyperLogLog
+    /// sketch, typically within a couple of percent of the exact count using a fixed 4KB of memory.
+    #[cfg(feature = "hll")]
+    pub fn approx_distinct(&self) -> u64 {
+        let mut hll = HyperLogLog::new();
+        for idx in 0..self.null_map.len() {
+            if let Slot::Value(datum) = self.slot(idx) {
+                hll.insert(&datum);
+            }
+        }
+        hll.estimate()
+    }
+
+    /// Iterates over every value stored in the column along with the top level row it belongs to.
+    /// Rows where the path is absent are skipped, and for nested columns a row may appear many times.
+    pub fn iter_with_row_index(&self) -> impl Iterator<Item = (usize, Slot)> + '_ {
+        self.indexes[0]
+            .iter()
+            .enumerate()
+            .map(|(idx, row)| (row as usize, self.slot(idx)))
+    }
+
+    /// Iterates over every value stored in the column as a `serde_json::Value`, in the same order
+    /// as `iter_with_row_index`.
+    /// Arrays and objects only have their contents stored in the child columns so come out as null.
+    pub fn iter_values(&self) -> impl Iterator<Item = Value> + '_ {
+        (0..self.null_map.len()).map(|idx| match self.slot(idx) {
+            Slot::Value(datum) => Value::from(&datum),
+            Slot::Object(_) | Slot::Array(_) => Value::Null,
+        })
+    }
+
+    /// Iterates over every value in a boolean column, in the same order as `iter_with_row_index`,
+    /// with nulls coming out as None.
+    /// Any non boolean values (eg in a union column) also come out as None.
+    pub fn iter_bool(&self) -> impl Iterator<Item = Option<bool>> + '_ {
+        (0..self.null_map.len()).map(|idx| match (&self.data, self.null_map[idx]) {
+            (_, true) => None,
+            (ColumnData::Bool(values), false) => Some(values[idx]),
+            (_, false) => match self.data.slot(idx) {
+                Slot::Value(datum) => datum.as_bool(),
+                Slot::Object(_) | Slot::Array(_) => None,
+            },
+        })
+    }
+
+    /// Iterates over every value in a numeric column as a float, in the same order as
+    /// `iter_with_row_index`, with nulls coming out as None.
+    /// Returns None if the column isn't numeric, union columns included.
+    pub fn iter_f64(&self) -> Option<impl Iterator<Item = Option<f64>> + '_> {
+        let numeric = matches!(
+            self.data.type_for(),
+            InternalType::TinyInt
+                | InternalType::SmallInt
+                | InternalType::Int
+                | InternalType::BigInt
+                | InternalType::Float
+        );
+        let values = (0..self.null_map.len()).map(|idx| match (&self.data, self.null_map[idx]) {
+            (_, true) => None,
+            (ColumnData::TinyInt(values), false) => Some(values[idx] as f64),
+            (ColumnData::SmallInt(values), false) => Some(values[idx] as f64),
+            (ColumnData::Int(values), false) => Some(values[idx] as f64),
+            (ColumnData::BigInt(values), false) => Some(values[idx] as f64),
+            (ColumnData::Float(values), false) => Some(values[idx]),
+            (_, false) => match self.data.slot(idx) {
+                Slot::Value(datum) => datum.as_f64(),
+                Slot::Object(_) | Slot::Array(_) => None,
+            },
+        });
+        numeric.then_some(values)
+    }
+
+    /// Iterates over every value in a string column without copying, in the same order as
+    /// `iter_with_row_index`, with nulls coming out as None.
+    /// Returns None if the column isn't a string column, union columns included.
+    pub fn iter_str(&self) -> Option<impl Iterator<Item = Option<&str>> + '_> {
+        let is_string = self.data.type_for() == InternalType::String;
+        let values = (0..self.null_map.len()).map(move |idx| {
+            let value = self.data.get_str(idx)?;
+            (!self.null_map[idx]).then_some(value)
+        });
+        is_string.then_some(values)
+    }
+
+    /// Borrows the string stored in a slot without copying it, None if the slot is null, out of
+    /// range or doesn't hold a string
+    pub fn get_str(&self, idx: usize) -> Option<&str> {
+        if self.null_map.get(idx)? {
+            return None;
+        }
+        self.data.get_str(idx)
+    }
+
+    /// One bit per top level row, set where the row holds a null in this column
+    pub(super) fn row_null_mask(&self, rows: usize) -> BitVec {
+        let mut mask = BitVec::from_elem(rows, false);
+        for (row, is_null) in self.indexes[0].iter().zip(self.null_map.iter()) {
+            if is_null {
+                mask.set(row as usize, true);
+            }
+        }
+        mask
+    }
+
+    /// Reads back the value stored in a single slot
+    pub fn slot(&self, idx: usize) -> Slot {
+        if self.null_map[idx] {
+            Slot::Value(Datum::Null)
+        } else {
+            self.data.slot(idx)
+        }
+    }
+
+    /// Grows the size recorded against an object slot, for when keys are added to an existing row
+    pub(super) fn grow_object(&mut self, idx: usize, keys: usize) {
+        self.expand();
+        match &mut self.data {
+            ColumnData::Object(sizes) => sizes[idx] += keys,
+            ColumnData::Union(vec) => {
+                if let Union::Object(size) = &mut vec[idx] {
+                    *size += keys;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Reserves space for at least `additional` more slots across the indexes, null map and data
+    pub fn reserve(&mut self, additional: usize) {
+        for index_buf in &mut self.indexes {
+            index_buf.reserve(additional);
+        }
+        self.null_map.reserve(additional);
+        self.data.reserve(additional);
+    }
+
+    /// Frees any spare capacity across the indexes, null map and data
+    pub fn shrink_to_fit(&mut self) {
+        for index_buf in &mut self.indexes {
+            index_buf.shrink_to_fit();
+        }
+        self.null_map.shrink_to_fit();
+        self.data.shrink_to_fit();
+    }
+
+    /// How many slots the column can hold in total before it next has to reallocate.
+    /// Sequential indexes and constant or dictionary encoded data don't grow per slot so don't
+    /// limit this.
+    pub fn capacity(&self) -> usize {
+        self.indexes
+            .iter()
+            .map(Indexes::capacity)
+            .chain([self.null_map.capacity(), self.data.capacity()])
+            .min()
+            .unwrap()
+    }
+
+    /// Re-encodes the data into its most compact form, this should be done once the column
+    /// has finished being written to.
+    pub(super) fn compact(&mut self) {
+        if matches!(
+            self.data,
+            ColumnData::Null
+                | ColumnData::Constant(_)
+                | ColumnData::Union(_)
+                | ColumnData::DictString { .. }
+        ) {
+            return;
+        }
+        let mut non_null = (0..self.null_map.len()).filter(|idx| !self.null_map[*idx]);
+        if let Some(first) = non_null.next() {
+            let value = self.data.slot(first);
+            if non_null.all(|idx| identical(&self.data.slot(idx), &value))
+                && self.null_map.len() > 1
+            {
+                let mut constant = self.data.empty_like();
+                constant.push_slot(&value);
+                self.data = ColumnData::Constant(Box::new(constant));
+                return;
+            }
+        }
+        // Low cardinality strings, where each distinct value repeats on average at least
+        // `DICTIONARY_MIN_REPEATS` times, are dictionary encoded
+        if let Some((dict, codes)) = self.data.to_dictionary(&self.null_map) {
+            if dict.len() * DICTIONARY_MIN_REPEATS <= codes.len() {
+                self.data = ColumnData::DictString { dict, codes };
+            }
+        }
+    }
+
+    /// Re-encodes a string column as a dictionary of its distinct values plus a code per slot, so
+    /// each distinct string is only stored once and scans can compare codes, see `find_string`.
+    /// `compact` does this automatically for low cardinality columns.
+    /// Does nothing for other types of column or columns holding only nulls, and like constant
+    /// columns the strings are decoded again if the column is written to.
+    pub fn dictionary_encode(&mut self) {
+        self.expand();
+        if let Some((dict, codes)) = self.data.to_dictionary(&self.null_map) {
+            self.data = ColumnData::DictString { dict, codes };
+        }
+    }
+
+    /// The slots holding the given string, in slot order.
+    /// Dictionary encoded columns only compare codes, the string being looked up once.
+    pub fn find_string(&self, value: &str) -> Vec<usize> {
+        let slots = 0..self.null_map.len();
+        match &self.data {
+            ColumnData::DictString { dict, codes } => match dict.iter().position(|s| s == value) {
+                Some(code) => slots
+                    .filter(|idx| codes[*idx] == code as u32 && !self.null_map[*idx])
+                    .collect(),
+                None => Vec::new(),
+            },
+            _ => slots
+                .filter(|idx| matches!(self.slot(*idx), Slot::Value(datum) if datum.as_str() == Some(value)))
+                .collect(),
+        }
+    }
+
+    /// Expands constant and dictionary encoded data back out into a value per slot
+    fn expand(&mut self) {
+        match &self.data {
+            ColumnData::Constant(value) => {
+                let slot = value.slot(0);
+                let mut data = value.empty_like();
+                for is_null in self.null_map.iter() {
+                    if is_null {
+                        data.push_datum(&Datum::Null);
+                    } else {
+                        data.push_slot(&slot);
+                    }
+                }
+                self.data = data;
+            }
+            ColumnData::DictString { dict, codes } => {
+                let mut data = match self.string_encoding {
+                    StringEncoding::Offsets => ColumnData::String(String::new(), Vec::new()),
+                    StringEncoding::LengthPrefixed => {
+                        ColumnData::LengthPrefixedString(String::new(), Vec::new())
+                    }
+                };
+                for (code, is_null) in codes.iter().zip(self.null_map.iter()) {
+                    if is_null {
+                        data.push_datum(&Datum::Null);
+                    } else {
+                        data.push_datum(&Datum::String(dict[*code as usize].clone()));
+                    }
+                }
+                self.data = data;
+            }
+            _ => {}
+        }
+    }
+
+    /// Appends every value in another column onto this one, widening the type if needed.
+    /// Types are widened exactly as when adding datums one by one, so eg a string column meeting a
+    /// float column becomes a union.
+    /// `index_offset` is added to the other column's top level row indexes, typically the number
+    /// of rows in this column's stripe.
+    ///
+    /// # Panics
+    /// If the columns are for paths with a different level of array nesting.
+    pub fn append(&mut self, other: &Column, index_offset: usize) {
+        assert_eq!(self.indexes.len(), other.indexes.len(), "columns differ in nesting");
+        let index_offset = index_offset as u32;
+        self.extend_from(other, 0..other.null_map.len(), |row| row + index_offset);
+    }
+
+    /// Adds `offset` to the top level row of every slot, for moving the column into a stripe
+    /// after `offset` existing rows
+    pub(super) fn shift_rows(&mut self, offset: usize) {
+        self.indexes[0].shift(offset as u32);
+    }
+
+    /// Like `append` but returns the merged column leaving both inputs untouched
+    pub fn concat(&self, other: &Column, index_offset: usize) -> Column {
+        let mut column = self.clone();
+        column.append(other, index_offset);
+        column
+    }
+
+    /// Converts every value in the column to the given type, eg narrowing integers or turning
+    /// numbers into strings and back. Nulls stay null, and casting to `Union` always succeeds.
+    /// Objects and arrays can't be converted as their contents live in child columns.
+    /// If any value can't be represented the column is left untouched.
+    pub fn cast_to(&mut self, target: InternalType) -> Result<(), CastError> {
+        let mut data = match target {
+            InternalType::Null | InternalType::Missing => ColumnData::Null,
+            InternalType::Bool => ColumnData::Bool(BitVec::new()),
+            InternalType::TinyInt => ColumnData::TinyInt(Vec::new()),
+            InternalType::SmallInt => ColumnData::SmallInt(Vec::new()),
+            InternalType::Int => ColumnData::Int(Vec::new()),
+            InternalType::BigInt => ColumnData::BigInt(Vec::new()),
+            InternalType::Float => ColumnData::Float(Vec::new()),
+            InternalType::String => match self.string_encoding {
+                StringEncoding::Offsets => ColumnData::String(String::new(), Vec::new()),
+                StringEncoding::LengthPrefixed => {
+                    ColumnData::LengthPrefixedString(String::new(), Vec::new())
+                }
+            },
+            InternalType::Object => ColumnData::Object(Vec::new()),
+            InternalType::Array => ColumnData::Array(Vec::new()),
+            InternalType::Union => ColumnData::Union(Vec::new()),
+        };
+        for idx in 0..self.null_map.len() {
+            let slot = self.slot(idx);
+            let cast = cast_slot(&slot, target).ok_or_else(|| CastError {
+                slot: idx,
+                value: match &slot {
+                    Slot::Value(datum) => datum.to_string(),
+                    Slot::Object(_) => "object".to_string(),
+                    Slot::Array(_) => "array".to_string(),
+                },
+                target,
+            })?;
+            data.push_slot(&cast);
+        }
+        self.data = data;
+        if self.bloom.is_some() {
+            self.build_bloom();
+        }
+        Ok(())
+    }
+
+    /// Replaces any NaN or infinite floats with the sentinel, or with null if there isn't one.
+    /// Formats like json can't represent non-finite floats, so this gives a stripe that reads back
+    /// the same whichever way it's serialized.
+    pub fn canonicalize_floats(&mut self, sentinel: Option<f64>) {
+        self.expand();
+        let null_map = &mut self.null_map;
+        match &mut self.data {
+            ColumnData::Float(vec) => {
+                for (idx, f) in vec.iter_mut().enumerate() {
+                    if !f.is_finite() {
+                        *f = sentinel.unwrap_or(0.0);
+                        null_map.set(idx, sentinel.is_none());
+                    }
+                }
+            }
+            ColumnData::Union(vec) => {
+                for (idx, value) in vec.iter_mut().enumerate() {
+                    match (&value, sentinel) {
+                        (Union::Float(f), _) if f.is_finite() => {}
+                        (Union::Float(_), Some(sentinel)) => *value = Union::Float(sentinel),
+                        (Union::Float(_), None) => {
+                            *value = Union::Null;
+                            null_map.set(idx, true);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => return,
+        }
+        if self.bloom.is_some() {
+            self.build_bloom();
+        }
+    }
+
+    /// Copies a range of slots from another column onto the end of this one, upcasting as needed.
+    /// `map_row` maps each slot's top level row into this column's stripe, the remaining
+    /// (array) indexes are copied as is.
+    pub(super) fn extend_from(
+        &mut self,
+        other: &Column,
+        slots: Range<usize>,
+        map_row: impl Fn(u32) -> u32,
+    ) {
+        for (level, (index_buf, other_buf)) in
+            self.indexes.iter_mut().zip(other.indexes.iter()).enumerate()
+        {
+            let indexes = slots.clone().map(|idx| other_buf.get(idx));
+            if level == 0 {
+                index_buf.extend(indexes.map(&map_row));
+            } else {
+                index_buf.extend(indexes);
+            }
+        }
+        if matches!(self.data, ColumnData::Float(_)) && other.has_inexact_ints(slots.clone()) {
+            self.up_cast(InternalType::Union);
+        }
+        self.up_cast(other.data.type_for());
+        if !self.data.extend_from(&other.data, slots.clone()) {
+            // Differing (but compatible) types, fall back to copying slot by slot
+            for idx in slots.clone() {
+                self.data.push_slot(&other.slot(idx));
+            }
+        }
+        for idx in slots {
+            self.null_map.push(other.null_map[idx]);
+            if let (Some(bloom), Slot::Value(datum)) = (&mut self.bloom, other.slot(idx)) {
+                bloom.insert(&datum);
+            }
+        }
+    }
+
+    /// Whether any of the slots hold an integer that would lose precision if widened to a float
+    pub(super) fn has_inexact_ints(&self, slots: Range<usize>) -> bool {
+        slots.into_iter().any(|idx| {
+            matches!(self.slot(idx), Slot::Value(Datum::BigInt(i)) if !exact_as_float(i))
+        })
     }
 
     /// Up-casts the columnData to be of the type needed to accept the passed in datum
-    fn up_cast(&mut self, data_type: InternalType) {
+    pub(super) fn up_cast(&mut self, data_type: InternalType) {
+        self.expand();
         match (&self.data, data_type) {
             // Null data or union columns are like wildcards.
             (_, InternalType::Missing) | (_, InternalType::Null) | (ColumnData::Union(_), _) => {}
@@ -78,14 +593,27 @@ impl Column {
             (ColumnData::Float(_), InternalType::Float)
             | (ColumnData::TinyInt(_), InternalType::TinyInt)
             | (ColumnData::SmallInt(_), InternalType::SmallInt)
+            | (ColumnData::Int(_), InternalType::Int)
+            | (ColumnData::BigInt(_), InternalType::BigInt)
             | (ColumnData::Array(_), InternalType::Array)
             | (ColumnData::String(_, _), InternalType::String)
+            | (ColumnData::LengthPrefixedString(_, _), InternalType::String)
             | (ColumnData::Object(_), InternalType::Object)
             | (ColumnData::Bool(_), InternalType::Bool) => {}
             // Compatible columns
             (ColumnData::SmallInt(_), InternalType::TinyInt)
-            | (ColumnData::Float(_), InternalType::TinyInt)
-            | (ColumnData::Float(_), InternalType::SmallInt) => {}
+            | (ColumnData::Int(_), InternalType::TinyInt | InternalType::SmallInt)
+            | (
+                ColumnData::BigInt(_),
+                InternalType::TinyInt | InternalType::SmallInt | InternalType::Int,
+            )
+            | (
+                ColumnData::Float(_),
+                InternalType::TinyInt
+                | InternalType::SmallInt
+                | InternalType::Int
+                | InternalType::BigInt,
+            ) => {}
             // Column type is null, just upcast, padding with default values
             (ColumnData::Null, InternalType::Bool) => {
                 let mut vec = BitVec::new();
@@ -98,6 +626,12 @@ impl Column {
             (ColumnData::Null, InternalType::SmallInt) => {
                 self.data = ColumnData::SmallInt(vec![0; self.null_map.len()]);
             }
+            (ColumnData::Null, InternalType::Int) => {
+                self.data = ColumnData::Int(vec![0; self.null_map.len()]);
+            }
+            (ColumnData::Null, InternalType::BigInt) => {
+                self.data = ColumnData::BigInt(vec![0; self.null_map.len()]);
+            }
             (ColumnData::Null, InternalType::Float) => {
                 self.data = ColumnData::Float(vec![0.0; self.null_map.len()]);
             }
@@ -108,68 +642,1072 @@ impl Column {
                 self.data = ColumnData::Array(vec![0; self.null_map.len()]);
             }
             (ColumnData::Null, InternalType::String) => {
-                self.data = ColumnData::String(String::new(), vec![0; self.null_map.len()]);
+                self.data = match self.string_encoding {
+                    StringEncoding::Offsets => {
+                        ColumnData::String(String::new(), vec![0; self.null_map.len()])
+                    }
+                    StringEncoding::LengthPrefixed => {
+                        ColumnData::LengthPrefixedString(String::new(), vec![0; self.null_map.len()])
+                    }
+                };
+            }
+            (ColumnData::Null, InternalType::Union) => {
+                self.data = ColumnData::Union(vec![Union::Null; self.null_map.len()]);
             }
             // Special cases to upcast numeric types
             (ColumnData::TinyInt(vec), InternalType::SmallInt) => {
                 self.data = ColumnData::SmallInt(vec.iter().map(|i| *i as i16).collect())
             }
+            (ColumnData::TinyInt(vec), InternalType::Int) => {
+                self.data = ColumnData::Int(vec.iter().map(|i| *i as i32).collect())
+            }
+            (ColumnData::TinyInt(vec), InternalType::BigInt) => {
+                self.data = ColumnData::BigInt(vec.iter().map(|i| *i as i64).collect())
+            }
             (ColumnData::TinyInt(vec), InternalType::Float) => {
                 self.data = ColumnData::Float(vec.iter().map(|i| *i as f64).collect())
             }
+            (ColumnData::SmallInt(vec), InternalType::Int) => {
+                self.data = ColumnData::Int(vec.iter().map(|i| *i as i32).collect())
+            }
+            (ColumnData::SmallInt(vec), InternalType::BigInt) => {
+                self.data = ColumnData::BigInt(vec.iter().map(|i| *i as i64).collect())
+            }
             (ColumnData::SmallInt(vec), InternalType::Float) => {
                 self.data = ColumnData::Float(vec.iter().map(|i| *i as f64).collect())
             }
+            (ColumnData::Int(vec), InternalType::BigInt) => {
+                self.data = ColumnData::BigInt(vec.iter().map(|i| *i as i64).collect())
+            }
+            (ColumnData::Int(vec), InternalType::Float) => {
+                self.data = ColumnData::Float(vec.iter().map(|i| *i as f64).collect())
+            }
+            // Integers a float can't hold exactly fall through to a union instead
+            (ColumnData::BigInt(vec), InternalType::Float)
+                if vec.iter().all(|i| exact_as_float(*i)) =>
+            {
+                self.data = ColumnData::Float(vec.iter().map(|i| *i as f64).collect())
+            }
 
-            // Otherwise we have to convert to a union type
-            (col, datum) => {
-                todo!("Tried to upcast {:?} to fit {:?}", col.type_for(), datum);
+            // Otherwise we have to convert to a union type, nulls carry across as `Union::Null`
+            _ => {
+                let mut union = ColumnData::Union(Vec::with_capacity(self.null_map.len()));
+                for idx in 0..self.null_map.len() {
+                    union.push_slot(&self.slot(idx));
+                }
+                self.data = union;
             }
         }
     }
 }
 
 /// The actual data inside one column
-#[derive(Debug, Serialize, Deserialize)]
+/// Variants are serialized by position, `DiskStripeWriter` relies on this order too.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ColumnData {
     Null, // If the whole column is null and untyped.
     TinyInt(Vec<i8>),
     SmallInt(Vec<i16>),
+    Int(Vec<i32>),
+    BigInt(Vec<i64>),
     Float(Vec<f64>),
     Bool(BitVec),
     String(String, Vec<usize>),
+    /// Strings with `u32` end offsets, written out as their lengths, see `StringEncoding`
+    LengthPrefixedString(String, #[serde(with = "crate::columnar::string_lengths")] Vec<u32>),
     Object(Vec<usize>),
     Array(Vec<usize>),
-    Union(Vec<Union>),
+    Union(#[serde(with = "crate::columnar::union_layout")] Vec<Union>),
+    /// Every non-null value is the same, holds a single value of the underlying type.
+    Constant(Box<ColumnData>),
+    /// Strings stored once each in `dict`, with each slot holding the position of its string.
+    /// Null slots have a code of 0.
+    DictString { dict: Vec<String>, codes: Vec<u32> },
 }
 
+/// How many times (on average) each distinct string must repeat for `Column::compact` to
+/// dictionary encode a column
+const DICTIONARY_MIN_REPEATS: usize = 2;
+
 impl ColumnData {
+    /// Reserves space for at least `additional` more values
+    fn reserve(&mut self, additional: usize) {
+        match self {
+            // Decoded before being written to
+            ColumnData::Null | ColumnData::Constant(_) | ColumnData::DictString { .. } => {}
+            ColumnData::TinyInt(vec) => vec.reserve(additional),
+            ColumnData::SmallInt(vec) => vec.reserve(additional),
+            ColumnData::Int(vec) => vec.reserve(additional),
+            ColumnData::BigInt(vec) => vec.reserve(additional),
+            ColumnData::Float(vec) => vec.reserve(additional),
+            ColumnData::Bool(vec) => vec.reserve(additional),
+            ColumnData::String(_, offsets) => offsets.reserve(additional),
+            ColumnData::LengthPrefixedString(_, ends) => ends.reserve(additional),
+            ColumnData::Object(sizes) | ColumnData::Array(sizes) => sizes.reserve(additional),
+            ColumnData::Union(vec) => vec.reserve(additional),
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        match self {
+            ColumnData::Null => {}
+            ColumnData::TinyInt(vec) => vec.shrink_to_fit(),
+            ColumnData::SmallInt(vec) => vec.shrink_to_fit(),
+            ColumnData::Int(vec) => vec.shrink_to_fit(),
+            ColumnData::BigInt(vec) => vec.shrink_to_fit(),
+            ColumnData::Float(vec) => vec.shrink_to_fit(),
+            ColumnData::Bool(vec) => vec.shrink_to_fit(),
+            ColumnData::String(str_buf, offsets) => {
+                str_buf.shrink_to_fit();
+                offsets.shrink_to_fit();
+            }
+            ColumnData::LengthPrefixedString(str_buf, ends) => {
+                str_buf.shrink_to_fit();
+                ends.shrink_to_fit();
+            }
+            ColumnData::Object(sizes) | ColumnData::Array(sizes) => sizes.shrink_to_fit(),
+            ColumnData::Union(vec) => vec.shrink_to_fit(),
+            ColumnData::Constant(value) => value.shrink_to_fit(),
+            ColumnData::DictString { dict, codes } => {
+                dict.shrink_to_fit();
+                codes.shrink_to_fit();
+            }
+        }
+    }
+
+    /// How many values can be held before reallocating
+    fn capacity(&self) -> usize {
+        match self {
+            ColumnData::Null | ColumnData::Constant(_) | ColumnData::DictString { .. } => usize::MAX,
+            ColumnData::TinyInt(vec) => vec.capacity(),
+            ColumnData::SmallInt(vec) => vec.capacity(),
+            ColumnData::Int(vec) => vec.capacity(),
+            ColumnData::BigInt(vec) => vec.capacity(),
+            ColumnData::Float(vec) => vec.capacity(),
+            ColumnData::Bool(vec) => vec.capacity(),
+            ColumnData::String(_, offsets) => offsets.capacity(),
+            ColumnData::LengthPrefixedString(_, ends) => ends.capacity(),
+            ColumnData::Object(sizes) | ColumnData::Array(sizes) => sizes.capacity(),
+            ColumnData::Union(vec) => vec.capacity(),
+        }
+    }
+
+    /// Checks there's a value for each slot
+    fn validate_len(&self, slots: usize) -> Result<(), OffsetError> {
+        let len = match self {
+            ColumnData::Null => return Ok(()),
+            ColumnData::Constant(value) => return value.validate_len(1),
+            ColumnData::TinyInt(vec) => vec.len(),
+            ColumnData::SmallInt(vec) => vec.len(),
+            ColumnData::Int(vec) => vec.len(),
+            ColumnData::BigInt(vec) => vec.len(),
+            ColumnData::Float(vec) => vec.len(),
+            ColumnData::Bool(vec) => vec.len(),
+            ColumnData::String(_, offsets) => offsets.len(),
+            ColumnData::LengthPrefixedString(_, ends) => ends.len(),
+            ColumnData::Object(sizes) | ColumnData::Array(sizes) => sizes.len(),
+            ColumnData::Union(vec) => vec.len(),
+            ColumnData::DictString { codes, .. } => codes.len(),
+        };
+        if len != slots {
+            return Err(OffsetError::ValueCountMismatch { expected: slots, actual: len });
+        }
+        Ok(())
+    }
+
+    fn validate_offsets(&self, slots: usize) -> Result<(), OffsetError> {
+        match self {
+            ColumnData::String(str_buf, offsets) => {
+                check_offsets(str_buf, offsets.iter().copied(), offsets.len(), slots)
+            }
+            ColumnData::LengthPrefixedString(str_buf, ends) => {
+                check_offsets(str_buf, ends.iter().map(|end| *end as usize), ends.len(), slots)
+            }
+            ColumnData::Constant(value) => value.validate_offsets(1),
+            ColumnData::DictString { dict, codes } => {
+                if codes.len() != slots {
+                    return Err(OffsetError::CountMismatch { expected: slots, actual: codes.len() });
+                }
+                match codes.iter().position(|code| *code as usize >= dict.len()) {
+                    Some(slot) => Err(OffsetError::UnknownCode {
+                        slot,
+                        code: codes[slot],
+                        dict_len: dict.len(),
+                    }),
+                    None => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Splits string data into its distinct values and a code per slot, returns None for other
+    /// types of data or if every slot is null.
+    fn to_dictionary(&self, null_map: &BitVec) -> Option<(Vec<String>, Vec<u32>)> {
+        if !matches!(self, ColumnData::String(_, _) | ColumnData::LengthPrefixedString(_, _))
+            || null_map.all()
+        {
+            return None;
+        }
+        let mut lookup = HashMap::new();
+        let mut dict = Vec::new();
+        let codes = (0..null_map.len())
+            .map(|idx| match self.slot(idx) {
+                Slot::Value(Datum::String(s)) if !null_map[idx] => {
+                    *lookup.entry(s).or_insert_with_key(|s| {
+                        dict.push(s.clone());
+                        (dict.len() - 1) as u32
+                    })
+                }
+                _ => 0,
+            })
+            .collect();
+        Some((dict, codes))
+    }
+
+    /// Reads back a single value, ignoring nulls
+    fn slot(&self, idx: usize) -> Slot {
+        match self {
+            ColumnData::Null => Slot::Value(Datum::Null),
+            ColumnData::TinyInt(vec) => Slot::Value(Datum::TinyInt(vec[idx])),
+            ColumnData::SmallInt(vec) => Slot::Value(Datum::SmallInt(vec[idx])),
+            ColumnData::Int(vec) => Slot::Value(Datum::Int(vec[idx])),
+            ColumnData::BigInt(vec) => Slot::Value(Datum::BigInt(vec[idx])),
+            ColumnData::Float(vec) => Slot::Value(Datum::Float(vec[idx])),
+            ColumnData::Bool(vec) => Slot::Value(Datum::Bool(vec[idx])),
+            ColumnData::String(str_buf, offsets) => {
+                let start = if idx == 0 { 0 } else { offsets[idx - 1] };
+                Slot::Value(Datum::String(str_buf[start..offsets[idx]].to_string()))
+            }
+            ColumnData::LengthPrefixedString(str_buf, ends) => {
+                let start = if idx == 0 { 0 } else { ends[idx - 1] as usize };
+                Slot::Value(Datum::String(str_buf[start..ends[idx] as usize].to_string()))
+            }
+            ColumnData::Object(sizes) => Slot::Object(sizes[idx]),
+            ColumnData::Array(sizes) => Slot::Array(sizes[idx]),
+            ColumnData::Union(vec) => match &vec[idx] {
+                Union::Null => Slot::Value(Datum::Null),
+                Union::Float(f) => Slot::Value(Datum::Float(*f)),
+                Union::Int(i) => Slot::Value(Datum::BigInt(*i)),
+                Union::Bool(b) => Slot::Value(Datum::Bool(*b)),
+                Union::String(s) => Slot::Value(Datum::String(s.clone())),
+                Union::Object(size) => Slot::Object(*size),
+                Union::Array(size) => Slot::Array(*size),
+            },
+            ColumnData::Constant(value) => value.slot(0),
+            ColumnData::DictString { dict, codes } => {
+                Slot::Value(Datum::String(dict[codes[idx] as usize].clone()))
+            }
+        }
+    }
+
+    /// Borrows the string stored in a slot without copying it, None if the slot is out of range
+    /// or this isn't string data.
+    /// Nulls are tracked by the column rather than the data, see `Column::get_str`.
+    /// Constant data holds a single value standing in for every slot so always returns it.
+    pub fn get_str(&self, idx: usize) -> Option<&str> {
+        match self {
+            ColumnData::String(str_buf, offsets) => {
+                let end = *offsets.get(idx)?;
+                let start = if idx == 0 { 0 } else { offsets[idx - 1] };
+                str_buf.get(start..end)
+            }
+            ColumnData::LengthPrefixedString(str_buf, ends) => {
+                let end = *ends.get(idx)? as usize;
+                let start = if idx == 0 { 0 } else { ends[idx - 1] as usize };
+                str_buf.get(start..end)
+            }
+            ColumnData::DictString { dict, codes } => {
+                dict.get(*codes.get(idx)? as usize).map(String::as_str)
+            }
+            ColumnData::Constant(value) => value.get_str(0),
+            _ => None,
+        }
+    }
+
+    /// Empty data of the same type
+    fn empty_like(&self) -> ColumnData {
+        match self {
+            ColumnData::Null => ColumnData::Null,
+            ColumnData::TinyInt(_) => ColumnData::TinyInt(Vec::new()),
+            ColumnData::SmallInt(_) => ColumnData::SmallInt(Vec::new()),
+            ColumnData::Int(_) => ColumnData::Int(Vec::new()),
+            ColumnData::BigInt(_) => ColumnData::BigInt(Vec::new()),
+            ColumnData::Float(_) => ColumnData::Float(Vec::new()),
+            ColumnData::Bool(_) => ColumnData::Bool(BitVec::new()),
+            ColumnData::String(_, _) => ColumnData::String(String::new(), Vec::new()),
+            ColumnData::LengthPrefixedString(_, _) => {
+                ColumnData::LengthPrefixedString(String::new(), Vec::new())
+            }
+            ColumnData::Object(_) => ColumnData::Object(Vec::new()),
+            ColumnData::Array(_) => ColumnData::Array(Vec::new()),
+            ColumnData::Union(_) => ColumnData::Union(Vec::new()),
+            ColumnData::Constant(value) => value.empty_like(),
+            ColumnData::DictString { .. } => ColumnData::String(String::new(), Vec::new()),
+        }
+    }
+
+    /// Pushes a datum onto the end of the data, nested values only have their size recorded.
+    /// The data must already have been upcast to fit the datum.
+    fn push_datum(&mut self, datum: &Datum) {
+        if let (ColumnData::LengthPrefixedString(str_buf, _), Some(str)) = (&*self, datum.as_str()) {
+            if u32::try_from(str_buf.len() + str.len()).is_err() {
+                self.switch_to_offsets();
+            }
+        }
+        match (self, datum) {
+            (ColumnData::Null, Datum::Null) => {}
+            (ColumnData::Null, _) => unreachable!(),
+            (ColumnData::Bool(vec), Datum::Bool(b)) => vec.push(*b),
+            (ColumnData::Bool(vec), Datum::Null) => vec.push(false),
+            (ColumnData::Bool(_), _) => unreachable!(),
+            (ColumnData::TinyInt(vec), Datum::TinyInt(i)) => vec.push(*i),
+            (ColumnData::TinyInt(vec), Datum::Null) => vec.push(0),
+            (ColumnData::TinyInt(_), _) => unreachable!(),
+            (ColumnData::SmallInt(vec), Datum::SmallInt(i)) => vec.push(*i),
+            (ColumnData::SmallInt(vec), Datum::TinyInt(i)) => vec.push(*i as i16),
+            (ColumnData::SmallInt(vec), Datum::Null) => vec.push(0),
+            (ColumnData::SmallInt(_), _) => unreachable!(),
+            (ColumnData::Int(vec), Datum::Int(i)) => vec.push(*i),
+            (ColumnData::Int(vec), Datum::SmallInt(i)) => vec.push(*i as i32),
+            (ColumnData::Int(vec), Datum::TinyInt(i)) => vec.push(*i as i32),
+            (ColumnData::Int(vec), Datum::Null) => vec.push(0),
+            (ColumnData::Int(_), _) => unreachable!(),
+            (ColumnData::BigInt(vec), Datum::BigInt(i)) => vec.push(*i),
+            (ColumnData::BigInt(vec), Datum::Int(i)) => vec.push(*i as i64),
+            (ColumnData::BigInt(vec), Datum::SmallInt(i)) => vec.push(*i as i64),
+            (ColumnData::BigInt(vec), Datum::TinyInt(i)) => vec.push(*i as i64),
+            (ColumnData::BigInt(vec), Datum::Null) => vec.push(0),
+            (ColumnData::BigInt(_), _) => unreachable!(),
+            (ColumnData::Float(vec), Datum::Float(f)) => vec.push(*f),
+            (ColumnData::Float(vec), Datum::BigInt(i)) => vec.push(*i as f64),
+            (ColumnData::Float(vec), Datum::Int(i)) => vec.push(*i as f64),
+            (ColumnData::Float(vec), Datum::SmallInt(i)) => vec.push(*i as f64),
+            (ColumnData::Float(vec), Datum::TinyInt(i)) => vec.push(*i as f64),
+            (ColumnData::Float(vec), Datum::Null) => vec.push(0.0),
+            (ColumnData::Float(_), _) => unreachable!(),
+            (ColumnData::String(str_buf, offsets), Datum::String(_) | Datum::SharedString(_)) => {
+                str_buf.push_str(datum.as_str().unwrap());
+                offsets.push(str_buf.len());
+            }
+            (ColumnData::String(str_buf, offsets), Datum::Null) => {
+                offsets.push(str_buf.len());
+            }
+            (ColumnData::String(_, _), _) => unreachable!(),
+            (
+                ColumnData::LengthPrefixedString(str_buf, ends),
+                Datum::String(_) | Datum::SharedString(_),
+            ) => {
+                str_buf.push_str(datum.as_str().unwrap());
+                // Checked above
+                ends.push(str_buf.len() as u32);
+            }
+            (ColumnData::LengthPrefixedString(str_buf, ends), Datum::Null) => {
+                ends.push(str_buf.len() as u32)
+            }
+            (ColumnData::LengthPrefixedString(_, _), _) => unreachable!(),
+            (ColumnData::Array(sizes), Datum::Array(arr)) => sizes.push(arr.len()),
+            (ColumnData::Array(sizes), Datum::Null) => sizes.push(0),
+            (ColumnData::Array(_), _) => unreachable!(),
+            (ColumnData::Object(sizes), Datum::Object(obj)) => sizes.push(obj.len()),
+            (ColumnData::Object(sizes), Datum::Null) => sizes.push(0),
+            (ColumnData::Object(_), _) => unreachable!(),
+            (ColumnData::Union(vec), Datum::Null) => vec.push(Union::Null),
+            (ColumnData::Union(_), Datum::Missing) => unreachable!(),
+            (ColumnData::Union(vec), Datum::Bool(b)) => vec.push(Union::Bool(*b)),
+            (ColumnData::Union(vec), Datum::TinyInt(i)) => vec.push(Union::Int(*i as i64)),
+            (ColumnData::Union(vec), Datum::SmallInt(i)) => vec.push(Union::Int(*i as i64)),
+            (ColumnData::Union(vec), Datum::Int(i)) => vec.push(Union::Int(*i as i64)),
+            (ColumnData::Union(vec), Datum::BigInt(i)) => vec.push(Union::Int(*i)),
+            (ColumnData::Union(vec), Datum::Float(f)) => vec.push(Union::Float(*f)),
+            (ColumnData::Union(vec), Datum::String(_) | Datum::SharedString(_)) => {
+                vec.push(Union::String(datum.as_str().unwrap().to_string()))
+            }
+            (ColumnData::Union(vec), Datum::Object(obj)) => vec.push(Union::Object(obj.len())),
+            (ColumnData::Union(vec), Datum::Array(arr)) => vec.push(Union::Array(arr.len())),
+            (ColumnData::Constant(_) | ColumnData::DictString { .. }, _) => unreachable!(),
+        }
+    }
+
+    /// Pushes a slot read from another column onto the end of the data
+    fn push_slot(&mut self, slot: &Slot) {
+        match (self, slot) {
+            (data, Slot::Value(datum)) => data.push_datum(datum),
+            (ColumnData::Object(sizes), Slot::Object(size))
+            | (ColumnData::Array(sizes), Slot::Array(size)) => sizes.push(*size),
+            (ColumnData::Union(vec), Slot::Object(size)) => vec.push(Union::Object(*size)),
+            (ColumnData::Union(vec), Slot::Array(size)) => vec.push(Union::Array(*size)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Copies a range of values from data of the same type, returns false if the types differ.
+    fn extend_from(&mut self, other: &ColumnData, range: Range<usize>) -> bool {
+        match (self, other) {
+            (ColumnData::Null, ColumnData::Null) => {}
+            (ColumnData::TinyInt(vec), ColumnData::TinyInt(other)) => {
+                vec.extend_from_slice(&other[range])
+            }
+            (ColumnData::SmallInt(vec), ColumnData::SmallInt(other)) => {
+                vec.extend_from_slice(&other[range])
+            }
+            (ColumnData::Int(vec), ColumnData::Int(other)) => vec.extend_from_slice(&other[range]),
+            (ColumnData::BigInt(vec), ColumnData::BigInt(other)) => {
+                vec.extend_from_slice(&other[range])
+            }
+            (ColumnData::Float(vec), ColumnData::Float(other)) => {
+                vec.extend_from_slice(&other[range])
+            }
+            (ColumnData::Bool(vec), ColumnData::Bool(other)) => {
+                for idx in range {
+                    vec.push(other[idx]);
+                }
+            }
+            (ColumnData::String(str_buf, offsets), ColumnData::String(other_buf, other_offsets)) => {
+                for idx in range {
+                    let start = if idx == 0 { 0 } else { other_offsets[idx - 1] };
+                    str_buf.push_str(&other_buf[start..other_offsets[idx]]);
+                    offsets.push(str_buf.len());
+                }
+            }
+            (
+                ColumnData::LengthPrefixedString(str_buf, ends),
+                ColumnData::LengthPrefixedString(other_buf, other_ends),
+            ) => {
+                let start = if range.start == 0 { 0 } else { other_ends[range.start - 1] as usize };
+                let end = if range.is_empty() { start } else { other_ends[range.end - 1] as usize };
+                if u32::try_from(str_buf.len() + end - start).is_err() {
+                    // Too big for u32 offsets, pushing slot by slot falls back to `String`
+                    return false;
+                }
+                let shift = str_buf.len();
+                str_buf.push_str(&other_buf[start..end]);
+                ends.extend(
+                    other_ends[range]
+                        .iter()
+                        .map(|end| (shift + *end as usize - start) as u32),
+                );
+            }
+            (ColumnData::Object(sizes), ColumnData::Object(other))
+            | (ColumnData::Array(sizes), ColumnData::Array(other)) => {
+                sizes.extend_from_slice(&other[range])
+            }
+            (ColumnData::Union(vec), ColumnData::Union(other)) => {
+                vec.extend_from_slice(&other[range])
+            }
+            (data, ColumnData::Null) => {
+                for _ in range {
+                    data.push_datum(&Datum::Null);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Switches length prefixed strings over to `usize` offsets, for when they outgrow `u32`
+    pub(super) fn switch_to_offsets(&mut self) {
+        if let ColumnData::LengthPrefixedString(str_buf, ends) = self {
+            let offsets = ends.iter().map(|end| *end as usize).collect();
+            *self = ColumnData::String(std::mem::take(str_buf), offsets);
+        }
+    }
+
     pub fn is_null(&self) -> bool {
         matches!(self, ColumnData::Null)
     }
 
+    /// Short name for how the values are laid out
+    pub(super) fn encoding_name(&self) -> &'static str {
+        match self {
+            ColumnData::LengthPrefixedString(_, _) => "length_prefixed",
+            ColumnData::Constant(_) => "constant",
+            ColumnData::DictString { .. } => "dictionary",
+            _ => "plain",
+        }
+    }
+
     pub(crate) fn type_for(&self) -> InternalType {
         match self {
             ColumnData::Null => InternalType::Null,
             ColumnData::TinyInt(_) => InternalType::TinyInt,
             ColumnData::SmallInt(_) => InternalType::SmallInt,
+            ColumnData::Int(_) => InternalType::Int,
+            ColumnData::BigInt(_) => InternalType::BigInt,
             ColumnData::Float(_) => InternalType::Float,
             ColumnData::Bool(_) => InternalType::Bool,
-            ColumnData::String(_, _) => InternalType::String,
+            ColumnData::String(_, _)
+            | ColumnData::LengthPrefixedString(_, _)
+            | ColumnData::DictString { .. } => InternalType::String,
             ColumnData::Object(_) => InternalType::Object,
             ColumnData::Array(_) => InternalType::Array,
             ColumnData::Union(_) => InternalType::Union,
+            ColumnData::Constant(value) => value.type_for(),
         }
     }
 }
 
+/// Checks a run of string end offsets against the buffer they index into
+fn check_offsets(
+    str_buf: &str,
+    ends: impl Iterator<Item = usize>,
+    count: usize,
+    slots: usize,
+) -> Result<(), OffsetError> {
+    if count != slots {
+        return Err(OffsetError::CountMismatch { expected: slots, actual: count });
+    }
+    let mut start = 0;
+    for (slot, end) in ends.enumerate() {
+        if end < start {
+            return Err(OffsetError::NotMonotonic { slot });
+        }
+        if end > str_buf.len() {
+            return Err(OffsetError::OutOfBounds { slot, offset: end, len: str_buf.len() });
+        }
+        if !str_buf.is_char_boundary(end) {
+            return Err(OffsetError::NotCharBoundary { slot, offset: end });
+        }
+        start = end;
+    }
+    Ok(())
+}
+
+/// Whether an integer survives being widened to a float, ie is within ±2^53
+fn exact_as_float(i: i64) -> bool {
+    const LIMIT: i64 = 1 << f64::MANTISSA_DIGITS;
+    (-LIMIT..=LIMIT).contains(&i)
+}
+
+/// Whether two slots hold exactly the same value. Unlike `==` floats are compared bit for bit, so
+/// -0.0 and 0.0 are told apart and a NaN matches itself.
+fn identical(a: &Slot, b: &Slot) -> bool {
+    match (a, b) {
+        (Slot::Value(Datum::Float(a)), Slot::Value(Datum::Float(b))) => a.to_bits() == b.to_bits(),
+        _ => a == b,
+    }
+}
+
+/// Converts a single slot for `Column::cast_to`, None if it can't be represented as the target
+fn cast_slot(slot: &Slot, target: InternalType) -> Option<Slot> {
+    let datum = match (slot, target) {
+        (Slot::Value(Datum::Null), _) | (_, InternalType::Union) => return Some(slot.clone()),
+        (Slot::Object(_), InternalType::Object) | (Slot::Array(_), InternalType::Array) => {
+            return Some(slot.clone())
+        }
+        (Slot::Value(datum), _) => datum,
+        (Slot::Object(_) | Slot::Array(_), _) => return None,
+    };
+    let float = match datum {
+        Datum::String(s) => s.parse::<f64>().ok().filter(|f| f.is_finite()),
+        _ => datum.as_f64(),
+    };
+    // Integers are parsed separately so large values don't lose precision going through a float
+    let integer = match datum {
+        Datum::String(s) => s.parse::<i64>().ok().or_else(|| Datum::Float(float?).as_i64()),
+        _ if datum.json_type() == JsonType::Number => datum.as_i64(),
+        _ => None,
+    };
+    let cast = match target {
+        InternalType::TinyInt => Datum::TinyInt(i8::try_from(integer?).ok()?),
+        InternalType::SmallInt => Datum::SmallInt(i16::try_from(integer?).ok()?),
+        InternalType::Int => Datum::Int(i32::try_from(integer?).ok()?),
+        InternalType::BigInt => Datum::BigInt(integer?),
+        InternalType::Float => Datum::Float(float?),
+        InternalType::String => match datum {
+            Datum::String(_) => datum.clone(),
+            Datum::Bool(_) => Datum::String(datum.to_string()),
+            _ if datum.json_type() == JsonType::Number => Datum::String(datum.to_string()),
+            _ => return None,
+        },
+        InternalType::Bool => match datum {
+            Datum::Bool(_) => datum.clone(),
+            Datum::String(s) => Datum::Bool(s.parse().ok()?),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(Slot::Value(cast))
+}
+
+/// A value `Column::cast_to` couldn't convert
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CastError {
+    pub slot: usize,
+    /// The value as json, or just `object`/`array` for nested values
+    pub value: String,
+    pub target: InternalType,
+}
+
+impl Display for CastError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "can't cast {} in slot {} to {:?}", self.value, self.slot, self.target)
+    }
+}
+
+impl Error for CastError {}
+
+/// Why a column (usually a string column's offsets) can't be trusted, see
+/// `Column::validate_offsets`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OffsetError {
+    /// A level of indexes doesn't have exactly one index per slot
+    IndexCountMismatch { level: usize, expected: usize, actual: usize },
+    /// The data doesn't have exactly one value per slot
+    ValueCountMismatch { expected: usize, actual: usize },
+    /// There isn't exactly one offset per slot
+    CountMismatch { expected: usize, actual: usize },
+    /// The string at `slot` ends before it starts
+    NotMonotonic { slot: usize },
+    /// The string at `slot` runs past the end of the buffer
+    OutOfBounds { slot: usize, offset: usize, len: usize },
+    /// The string at `slot` ends partway through a utf-8 character
+    NotCharBoundary { slot: usize, offset: usize },
+    /// The dictionary code at `slot` is past the end of the dictionary
+    UnknownCode { slot: usize, code: u32, dict_len: usize },
+}
+
+impl Display for OffsetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OffsetError::IndexCountMismatch { level, expected, actual } => {
+                write!(f, "expected {expected} indexes at level {level} but found {actual}")
+            }
+            OffsetError::ValueCountMismatch { expected, actual } => {
+                write!(f, "expected {expected} values but found {actual}")
+            }
+            OffsetError::CountMismatch { expected, actual } => {
+                write!(f, "expected {expected} string offsets but found {actual}")
+            }
+            OffsetError::NotMonotonic { slot } => {
+                write!(f, "string offset for slot {slot} goes backwards")
+            }
+            OffsetError::OutOfBounds { slot, offset, len } => write!(
+                f,
+                "string offset {offset} for slot {slot} is past the end of the {len} byte buffer"
+            ),
+            OffsetError::NotCharBoundary { slot, offset } => {
+                write!(f, "string offset {offset} for slot {slot} isn't on a char boundary")
+            }
+            OffsetError::UnknownCode { slot, code, dict_len } => write!(
+                f,
+                "dictionary code {code} for slot {slot} is past the end of the {dict_len} entry dictionary"
+            ),
+        }
+    }
+}
+
+impl Error for OffsetError {}
+
 /// Very similar to a datum but Arrays and Objects only contain some metadata here.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Union {
     Null,
     Float(f64),
+    /// Integers of every width, kept exact rather than widened to a float
+    Int(i64),
     Bool(bool),
     String(String),
     Array(usize),
     Object(usize),
 }
+
+/// How string columns are laid out, chosen when building a stripe
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum StringEncoding {
+    /// The end offset of each string within a shared buffer
+    #[default]
+    Offsets,
+    /// The length of each string as a `u32`, half the size of `Offsets` on 64 bit targets.
+    /// In memory these are kept as `u32` end offsets so random access is as cheap as `Offsets`.
+    /// A column whose strings add up to 4 GiB or more falls back to `Offsets`.
+    LengthPrefixed,
+}
+
+/// A single value read back out of a column.
+/// Like with `Union`, arrays and objects only carry their size, their contents live in child columns.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Slot {
+    Value(Datum),
+    Object(usize),
+    Array(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::columnar::column::{CastError, Column, ColumnData, OffsetError, Slot, Union};
+    use crate::columnar::indexes::Indexes;
+    use crate::columnar::{parse_path, StringEncoding, Stripe};
+    use crate::datum::{Datum, InternalType};
+    use serde_json::json;
+
+    #[test]
+    fn length_prefixed_round_trip() {
+        let rows: Vec<Datum> = [
+            json!({"s": "foo"}),
+            json!({"s": ""}),
+            json!({"s": null}),
+            json!({}),
+            json!({"s": "héllo"}),
+            json!({"s": ""}),
+            json!({"s": "bar"}),
+        ]
+        .into_iter()
+        .map(Datum::from)
+        .collect();
+        let mut stripe = Stripe::new().with_string_encoding(StringEncoding::LengthPrefixed);
+        stripe.push_data(&rows);
+
+        // The row without the key has no slot
+        let column = stripe.get_column_str("s").unwrap();
+        assert_eq!(column.data.encoding_name(), "length_prefixed");
+        assert_eq!(column.get_str(3), Some("héllo"));
+        assert_eq!(column.get_str(2), None);
+        assert_eq!(stripe.to_data(), rows);
+
+        let bytes = bincode::serialize(&stripe).unwrap();
+        let stripe: Stripe = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(stripe.get_column_str("s").unwrap().get_str(5), Some("bar"));
+        assert_eq!(stripe.to_data(), rows);
+    }
+
+    #[test]
+    fn mismatched_lengths_are_rejected() {
+        let mut stripe = Stripe::new();
+        stripe.push_data(&[Datum::from(json!({"n": 1, "a": [1, 2]})), Datum::from(json!({"n": 2}))]);
+        let column = stripe.get_column_str("n").unwrap().clone();
+        assert!(column.validate_offsets().is_ok());
+
+        let mut extra_slot = column.clone();
+        extra_slot.null_map.push(false);
+        let mut short_indexes = column.clone();
+        short_indexes.indexes[0] = Indexes::Sequential(0..1);
+        let mut short_data = column.clone();
+        short_data.data = ColumnData::TinyInt(vec![1]);
+        let mut bad_constant = column.clone();
+        bad_constant.data = ColumnData::Constant(Box::new(ColumnData::TinyInt(vec![1, 2])));
+        for (column, expected) in [
+            (extra_slot, OffsetError::IndexCountMismatch { level: 0, expected: 3, actual: 2 }),
+            (short_indexes, OffsetError::IndexCountMismatch { level: 0, expected: 2, actual: 1 }),
+            (short_data, OffsetError::ValueCountMismatch { expected: 2, actual: 1 }),
+            (bad_constant, OffsetError::ValueCountMismatch { expected: 1, actual: 2 }),
+        ] {
+            assert_eq!(column.validate_offsets(), Err(expected.clone()));
+            let bytes = bincode::serialize(&column).unwrap();
+            let err = bincode::deserialize::<Column>(&bytes).unwrap_err();
+            assert!(err.to_string().contains(&expected.to_string()), "{err}");
+        }
+
+        // Columns under arrays need a level of indexes per array
+        stripe.columns.get_mut(&parse_path("a.[]").unwrap()).unwrap().indexes.pop();
+        let bytes = bincode::serialize(&stripe).unwrap();
+        let err = bincode::deserialize::<Stripe>(&bytes).unwrap_err();
+        assert!(err.to_string().contains("a.[] has 1 levels of indexes but its path needs 2"), "{err}");
+    }
+
+    #[test]
+    fn constant_columns_compact_around_nulls() {
+        let rows: Vec<Datum> = (0..10)
+            .map(|i| {
+                if i % 3 == 0 {
+                    Datum::from(json!({"env": null, "id": i}))
+                } else if i % 3 == 1 {
+                    Datum::from(json!({"id": i}))
+                } else {
+                    Datum::from(json!({"env": "prod", "id": i}))
+                }
+            })
+            .collect();
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        stripe.compact();
+
+        let env = stripe.get_column(&parse_path("env").unwrap()).unwrap();
+        let ColumnData::Constant(value) = &env.data else {
+            panic!("expected a constant column, got {:?}", env.data);
+        };
+        assert_eq!(value.slot(0), Slot::Value(Datum::String("prod".to_string())));
+        let id = stripe.get_column(&parse_path("id").unwrap()).unwrap();
+        assert!(!matches!(id.data, ColumnData::Constant(_)));
+        assert_eq!(stripe.to_data(), rows);
+
+        let bytes = bincode::serialize(&stripe).unwrap();
+        let stripe: Stripe = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(stripe.to_data(), rows);
+    }
+
+    #[test]
+    fn bad_string_offsets_are_rejected() {
+        let rows = [json!({"s": "ab"}), json!({"s": "é"}), json!({"s": "cd"})].map(Datum::from);
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        let column = stripe.get_column_str("s").unwrap().clone();
+        let ColumnData::String(str_buf, offsets) = &column.data else {
+            panic!("expected a string column, got {:?}", column.data);
+        };
+        assert_eq!((str_buf.as_str(), offsets.as_slice()), ("abécd", &[2, 4, 6][..]));
+
+        let with_ends = |ends: Vec<usize>| {
+            let mut column = column.clone();
+            column.data = ColumnData::String(str_buf.clone(), ends);
+            column
+        };
+        let mut length_prefixed = column.clone();
+        length_prefixed.data = ColumnData::LengthPrefixedString(str_buf.clone(), vec![2, 3, 6]);
+        let mut unknown_code = column.clone();
+        unknown_code.data = ColumnData::DictString {
+            dict: vec!["ab".to_string(), "é".to_string()],
+            codes: vec![0, 1, 2],
+        };
+        for (column, expected) in [
+            (with_ends(vec![2, 1, 6]), OffsetError::NotMonotonic { slot: 1 }),
+            (with_ends(vec![2, 4, 9]), OffsetError::OutOfBounds { slot: 2, offset: 9, len: 6 }),
+            (with_ends(vec![2, 3, 6]), OffsetError::NotCharBoundary { slot: 1, offset: 3 }),
+            (length_prefixed, OffsetError::NotCharBoundary { slot: 1, offset: 3 }),
+            (unknown_code, OffsetError::UnknownCode { slot: 2, code: 2, dict_len: 2 }),
+        ] {
+            let bytes = bincode::serialize(&column).unwrap();
+            let err = bincode::deserialize::<Column>(&bytes).unwrap_err();
+            assert!(err.to_string().contains(&expected.to_string()), "{err}");
+        }
+    }
+
+    #[test]
+    fn mixed_types_up_cast_to_a_union() {
+        let rows = [json!({"x": 1}), json!({"x": "hi"}), json!({"x": true})].map(Datum::from);
+        let mut stripe = Stripe::new();
+        for row in &rows {
+            stripe.push_datum(row);
+        }
+        let column = stripe.get_column_str("x").unwrap();
+        let ColumnData::Union(values) = &column.data else {
+            panic!("expected a union column, got {:?}", column.data);
+        };
+        assert_eq!(values, &[Union::Int(1), Union::String("hi".to_string()), Union::Bool(true)]);
+        assert_eq!(column.null_map.iter().collect::<Vec<_>>(), [false, false, false]);
+        // Unions hold every integer as an i64
+        let mut expected = rows.to_vec();
+        let Datum::Object(first) = &mut expected[0] else { panic!("expected an object") };
+        first.insert("x".to_string(), Datum::BigInt(1));
+        assert_eq!(stripe.to_data(), expected);
+    }
+
+    #[test]
+    fn signed_zeros_are_not_constant() {
+        let rows = [0.0, -0.0, 0.0].map(|f| {
+            Datum::Object([("f".to_string(), Datum::Float(f))].into_iter().collect())
+        });
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        stripe.compact();
+        let column = stripe.get_column_str("f").unwrap();
+        assert!(!matches!(column.data, ColumnData::Constant(_)));
+        let signs: Vec<bool> = stripe
+            .to_data()
+            .iter()
+            .map(|row| matches!(row.get("f"), Some(Datum::Float(f)) if f.is_sign_negative()))
+            .collect();
+        assert_eq!(signs, [false, true, false]);
+
+        // The same bits, NaNs included, still collapse
+        let rows = [f64::NAN, f64::NAN].map(|f| {
+            Datum::Object([("f".to_string(), Datum::Float(f))].into_iter().collect())
+        });
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        stripe.compact();
+        let column = stripe.get_column_str("f").unwrap();
+        assert!(matches!(column.data, ColumnData::Constant(_)));
+    }
+
+    #[test]
+    fn nested_values_know_their_row() {
+        let rows = [json!({"n": [1, 2]}), json!({"n": []}), json!({}), json!({"n": [3, null]})];
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows.map(Datum::from));
+        let column = stripe.get_column_str("n.[]").unwrap();
+        let value = |i| Slot::Value(Datum::TinyInt(i));
+        assert_eq!(
+            column.iter_with_row_index().collect::<Vec<_>>(),
+            [(0, value(1)), (0, value(2)), (3, value(3)), (3, Slot::Value(Datum::Null))]
+        );
+        let column = stripe.get_column_str("n").unwrap();
+        assert_eq!(
+            column.iter_with_row_index().collect::<Vec<_>>(),
+            [(0, Slot::Array(2)), (1, Slot::Array(0)), (3, Slot::Array(2))]
+        );
+    }
+
+    #[test]
+    fn large_integers_and_floats_share_a_union() {
+        let large = Datum::from(json!({"a": 9007199254740993_i64}));
+        let float = Datum::from(json!({"a": 1.5}));
+        for rows in [[large.clone(), float.clone()], [float.clone(), large.clone()]] {
+            let mut stripe = Stripe::new();
+            stripe.push_data(&rows);
+            assert_eq!(stripe.to_data(), rows);
+        }
+
+        // Integers within ±2^53 still widen to a float column
+        let mut stripe = Stripe::new();
+        stripe.push_value(json!({"a": 9007199254740992_i64}));
+        stripe.push_datum(&float);
+        let column = stripe.get_column_str("a").unwrap();
+        assert!(matches!(column.data, ColumnData::Float(_)));
+
+        // Until a larger one is merged in from another stripe
+        let mut other = Stripe::new();
+        other.push_datum(&large);
+        stripe.merge(other);
+        let column = stripe.get_column_str("a").unwrap();
+        assert!(matches!(column.data, ColumnData::Union(_)));
+        let exact = Datum::from(json!({"a": 9007199254740992.0}));
+        assert_eq!(stripe.to_data(), [exact, float.clone(), large.clone()]);
+
+        // The schema unified across stripes agrees with what merging them gives
+        let stripes = [large, float].map(|row| {
+            let mut stripe = Stripe::new();
+            stripe.push_datum(&row);
+            stripe
+        });
+        let unified = Stripe::unified_schema(&stripes);
+        let merged = Stripe::concat_compatible(stripes);
+        assert_eq!(unified, Stripe::unified_schema(std::slice::from_ref(&merged)));
+        assert_eq!(unified[1].1, InternalType::Union);
+    }
+
+    #[test]
+    fn values_come_out_as_json() {
+        let mut stripe = Stripe::new();
+        for row in [
+            json!({"a": 1, "o": {"x": 2}, "l": [1.5, null]}),
+            json!({"a": "s", "l": []}),
+            json!({"a": null}),
+        ] {
+            stripe.push_value(row);
+        }
+        let values = |path| stripe.get_column_str(path).unwrap().iter_values().collect::<Vec<_>>();
+        assert_eq!(values("a"), [json!(1), json!("s"), json!(null)]);
+        assert_eq!(values("l.[]"), [json!(1.5), json!(null)]);
+        // Only the contents of objects and arrays are stored
+        assert_eq!(values("o"), [json!(null)]);
+        assert_eq!(values("l"), [json!(null), json!(null)]);
+    }
+
+    #[cfg(feature = "hll")]
+    #[test]
+    fn approx_distinct_is_close_to_the_exact_count() {
+        let mut stripe = Stripe::new();
+        for i in 0..20_000 {
+            stripe.push_value(json!({"n": i % 5_000, "s": format!("s{}", i % 300), "z": null}));
+        }
+        for (path, exact) in [("n", 5_000.0), ("s", 300.0)] {
+            let estimate = stripe.get_column_str(path).unwrap().approx_distinct() as f64;
+            assert!((estimate - exact).abs() / exact < 0.05, "{path}: {estimate}");
+        }
+        // Nulls aren't counted
+        assert_eq!(stripe.get_column_str("z").unwrap().approx_distinct(), 0);
+    }
+
+    #[test]
+    fn concat_leaves_its_inputs_untouched() {
+        let column = |rows: &[serde_json::Value]| {
+            let mut stripe = Stripe::new();
+            for row in rows {
+                stripe.push_value(row.clone());
+            }
+            stripe.get_column_str("v").unwrap().clone()
+        };
+        let first = column(&[json!({"v": 1}), json!({"v": null})]);
+        let second = column(&[json!({"v": "x"}), json!({}), json!({"v": 2})]);
+        let (first_before, second_before) = (format!("{first:?}"), format!("{second:?}"));
+
+        let merged = first.concat(&second, 2);
+        assert_eq!(
+            merged.iter_with_row_index().collect::<Vec<_>>(),
+            [
+                (0, Slot::Value(Datum::BigInt(1))),
+                (1, Slot::Value(Datum::Null)),
+                (2, Slot::Value(Datum::String("x".to_string()))),
+                (4, Slot::Value(Datum::BigInt(2))),
+            ]
+        );
+        assert!(matches!(merged.data, ColumnData::Union(_)));
+        assert_eq!(format!("{first:?}"), first_before);
+        assert_eq!(format!("{second:?}"), second_before);
+    }
+
+    #[test]
+    fn booleans_iterate_with_nulls_as_none() {
+        let mut stripe = Stripe::new();
+        for value in [json!(true), json!(null), json!(false), json!(null)] {
+            stripe.push_value(json!({"b": value, "t": value.as_bool().map(|_| true), "u": value}));
+        }
+        stripe.push_value(json!({"u": 1}));
+        let bools = |stripe: &Stripe, path| {
+            stripe.get_column_str(path).unwrap().iter_bool().collect::<Vec<_>>()
+        };
+        assert_eq!(bools(&stripe, "b"), [Some(true), None, Some(false), None]);
+        // Other types in a union come out as None too
+        assert_eq!(bools(&stripe, "u"), [Some(true), None, Some(false), None, None]);
+
+        // Compacted columns iterate just the same
+        stripe.compact();
+        assert!(matches!(stripe.get_column_str("t").unwrap().data, ColumnData::Constant(_)));
+        assert_eq!(bools(&stripe, "t"), [Some(true), None, Some(true), None]);
+    }
+
+    #[test]
+    fn columns_cast_between_types() {
+        let mut stripe = Stripe::new();
+        for row in [
+            json!({"s": 300, "f": 1.5, "o": {"k": 1}}),
+            json!({"s": null, "f": 2, "o": null}),
+            json!({"s": -2, "f": null}),
+        ] {
+            stripe.push_value(row);
+        }
+        let column = |path| stripe.get_column_str(path).unwrap().clone();
+        let values = |column: &Column| {
+            column.iter_with_row_index().map(|(_, slot)| slot).collect::<Vec<_>>()
+        };
+
+        let mut small = column("s");
+        assert!(matches!(small.data, ColumnData::SmallInt(_)));
+        small.cast_to(InternalType::Float).unwrap();
+        assert!(matches!(small.data, ColumnData::Float(_)));
+        let floats = [Datum::Float(300.0), Datum::Null, Datum::Float(-2.0)];
+        assert_eq!(values(&small), floats.map(Slot::Value));
+
+        let mut numbers = column("f");
+        numbers.cast_to(InternalType::String).unwrap();
+        // The 2 was widened to a float on the way in
+        let strings = [Datum::String("1.5".into()), Datum::String("2.0".into()), Datum::Null];
+        assert_eq!(values(&numbers), strings.map(Slot::Value));
+
+        // Objects can't become numbers, and a failed cast leaves the column as it was
+        let mut objects = column("o");
+        let before = format!("{objects:?}");
+        assert_eq!(
+            objects.cast_to(InternalType::Float),
+            Err(CastError { slot: 0, value: "object".to_string(), target: InternalType::Float })
+        );
+        assert_eq!(format!("{objects:?}"), before);
+    }
+
+    #[test]
+    fn reserve_grows_capacity_without_touching_values() {
+        let mut stripe = Stripe::new();
+        for row in [json!({"l": [1, 2]}), json!({"l": [null]}), json!({})] {
+            stripe.push_value(row);
+        }
+        let mut column = stripe.get_column_str("l.[]").unwrap().clone();
+        let before: Vec<_> = column.iter_with_row_index().collect();
+        let len = column.null_map.len();
+
+        column.reserve(1_000);
+        assert!(column.capacity() >= len + 1_000, "{}", column.capacity());
+        assert_eq!(column.null_map.len(), len);
+        assert_eq!(column.iter_with_row_index().collect::<Vec<_>>(), before);
+    }
+}
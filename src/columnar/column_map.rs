@@ -0,0 +1,74 @@
+//! Serialization for a stripe's columns.
+//! The columns are keyed by path, but formats like json only allow string map keys, so the map is
+//! written as a sequence of `(path, column)` pairs instead. Under bincode a map and a sequence of
+//! pairs are encoded identically, so this doesn't change the binary format.
+use crate::columnar::column::Column;
+use crate::columnar::{path_depth, path_to_string, Path};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+
+/// A stripe's columns, borrowed for serializing
+pub(super) struct ColumnsRef<'a>(pub &'a BTreeMap<Path, Column>);
+
+impl Serialize for ColumnsRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0)
+    }
+}
+
+/// A stripe's columns, as deserialized
+pub(super) struct Columns(pub BTreeMap<Path, Column>);
+
+impl<'de> Deserialize<'de> for Columns {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pairs = Vec::<(Path, Column)>::deserialize(deserializer)?;
+        // Each array in the path adds a level of indexes on top of the row
+        for (path, column) in &pairs {
+            if column.indexes().len() != path_depth(path) {
+                return Err(D::Error::custom(format!(
+                    "column {} has {} levels of indexes but its path needs {}",
+                    path_to_string(path),
+                    column.indexes().len(),
+                    path_depth(path)
+                )));
+            }
+        }
+        Ok(Columns(pairs.into_iter().collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::columnar::{Path, PathComponent, Stripe};
+    use crate::datum::Datum;
+    use serde_json::json;
+
+    #[test]
+    fn paths_round_trip_through_bincode_and_json() {
+        let awkward = "quote\" slash\\ newline\n dot. brackets[] \u{e9}";
+        let path: Path = vec![
+            PathComponent::Key(awkward.to_string()),
+            PathComponent::Array,
+            PathComponent::Key(String::new()),
+        ];
+        let bytes = bincode::serialize(&path).unwrap();
+        assert_eq!(bincode::deserialize::<Path>(&bytes).unwrap(), path);
+        let json = serde_json::to_string(&path).unwrap();
+        assert_eq!(serde_json::from_str::<Path>(&json).unwrap(), path);
+
+        // And so do stripes keyed by them
+        let row = Datum::from(json!({awkward: [{"": 1}]}));
+        let mut stripe = Stripe::new();
+        stripe.push_datum(&row);
+        assert!(stripe.get_column(&path).is_some());
+        let bytes = bincode::serialize(&stripe).unwrap();
+        let json = serde_json::to_string(&stripe).unwrap();
+        for read_back in [
+            bincode::deserialize::<Stripe>(&bytes).unwrap(),
+            serde_json::from_str::<Stripe>(&json).unwrap(),
+        ] {
+            assert_eq!(read_back.to_data(), std::slice::from_ref(&row));
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use crate::columnar::Stripe;
+use bit_vec::BitVec;
+use std::io::BufRead;
+
+impl Stripe {
+    /// Appends the rows of another stripe onto the end of this one.
+    /// Columns whose types have drifted between the stripes are widened to fit both, eg a
+    /// `TinyInt` column meeting a `Float` column ends up as `Float`.
+    /// Row ids from the other stripe are copied as is, so they're relative to the stripe they were
+    /// written in.
+    pub fn append(&mut self, other: &Stripe) {
+        let row_offset = self.count;
+        for (path, other_column) in &other.columns {
+            self.column_entry(path).append(other_column, row_offset);
+        }
+        self.count += other.count;
+    }
+
+    /// Like `append` but takes ownership of the other stripe, so columns only it has are moved
+    /// across rather than copied.
+    pub fn merge(&mut self, other: Stripe) {
+        let row_offset = self.count;
+        for (path, mut other_column) in other.columns {
+            match self.columns.get_mut(&path) {
+                Some(column) => column.append(&other_column, row_offset),
+                None => {
+                    other_column.shift_rows(row_offset);
+                    self.columns.insert(path, other_column);
+                }
+            }
+        }
+        self.count += other.count;
+    }
+
+    /// Splits the stripe into the first `row` rows and the rest, the inverse of `append`.
+    /// Rows in the second stripe are renumbered to start from 0, though as with `append` row ids
+    /// are left as is. Columns with a bloom filter get a new one in each half.
+    ///
+    /// # Panics
+    /// If `row` is past the end of the stripe.
+    pub fn split_at(&self, row: usize) -> (Stripe, Stripe) {
+        assert!(row <= self.count, "split at row {row} of a {} row stripe", self.count);
+        let mut first = Stripe {
+            count: row,
+            ..self.empty_copy()
+        };
+        let mut second = Stripe {
+            count: self.count - row,
+            ..self.empty_copy()
+        };
+        let offset = row as u32;
+        for (path, column) in &self.columns {
+            let split = column.row_range(row).start;
+            if split > 0 {
+                first.column_entry(path).extend_from(column, 0..split, |row| row);
+            }
+            if split < column.null_map.len() {
+                second
+                    .column_entry(path)
+                    .extend_from(column, split..column.null_map.len(), |row| row - offset);
+            }
+        }
+        self.copy_blooms(&mut first);
+        self.copy_blooms(&mut second);
+        (first, second)
+    }
+
+    /// Partitions the rows into stripes of at most `rows_per_stripe` rows, each standing on its own
+    /// with its rows numbered from 0. Only the last stripe can be smaller, and an empty stripe
+    /// splits into no stripes at all. Columns with a bloom filter get a new one in each stripe.
+    pub fn split(self, rows_per_stripe: usize) -> Vec<Stripe> {
+        let rows_per_stripe = rows_per_stripe.max(1);
+        let mut stripes: Vec<Stripe> = (0..self.count)
+            .step_by(rows_per_stripe)
+            .map(|first_row| Stripe {
+                count: rows_per_stripe.min(self.count - first_row),
+                ..self.empty_copy()
+            })
+            .collect();
+        for (path, column) in &self.columns {
+            let mut start = 0;
+            for (first_row, stripe) in (0..self.count).step_by(rows_per_stripe).zip(&mut stripes) {
+                let end = column.row_range(first_row + stripe.count).start;
+                if end > start {
+                    let offset = first_row as u32;
+                    stripe
+                        .column_entry(path)
+                        .extend_from(column, start..end, |row| row - offset);
+                }
+                start = end;
+            }
+        }
+        for stripe in &mut stripes {
+            self.copy_blooms(stripe);
+        }
+        stripes
+    }
+
+    /// Keeps only the rows whose bit is set in `mask`, eg one combined from `null_mask`s.
+    /// The rows kept are renumbered to start from 0, but row ids are copied as is so they still
+    /// lead back to the rows as they were ingested. Columns with a bloom filter get a new one
+    /// covering just the rows kept.
+    ///
+    /// # Panics
+    /// If the mask doesn't have a bit per row.
+    pub fn apply_mask(&self, mask: &BitVec) -> Stripe {
+        assert_eq!(mask.len(), self.count, "mask of {} bits for a {} row stripe", mask.len(), self.count);
+        // Runs of consecutive rows to keep as (first row, end row, first row once filtered),
+        // each run is copied over in one go
+        let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+        let mut kept = 0;
+        for row in (0..self.count).filter(|row| mask[*row]) {
+            match runs.last_mut() {
+                Some((_, end, _)) if *end == row => *end += 1,
+                _ => runs.push((row, row + 1, kept)),
+            }
+            kept += 1;
+        }
+
+        let mut filtered = Stripe {
+            count: kept,
+            ..self.empty_copy()
+        };
+        for (path, column) in &self.columns {
+            for (start, end, new_start) in &runs {
+                let slots = column.row_range(*start).start..column.row_range(*end - 1).end;
+                if !slots.is_empty() {
+                    let offset = (start - new_start) as u32;
+                    filtered
+                        .column_entry(path)
+                        .extend_from(column, slots, |row| row - offset);
+                }
+            }
+        }
+        self.copy_blooms(&mut filtered);
+        filtered
+    }
+
+    /// Builds bloom filters on the columns of `part` (a stripe cut from this one) whose column
+    /// here has one, see `build_blooms`
+    fn copy_blooms(&self, part: &mut Stripe) {
+        for (path, column) in &mut part.columns {
+            if self.columns[path].has_bloom() {
+                column.build_bloom();
+            }
+        }
+    }
+
+    /// Concatenates stripes into a single stripe, taking its options from the first stripe.
+    /// See `append` for how types that differ between stripes are handled.
+    pub fn concat_compatible(stripes: impl IntoIterator<Item = Stripe>) -> Stripe {
+        let mut stripes = stripes.into_iter();
+        let mut result = stripes.next().unwrap_or_default();
+        for stripe in stripes {
+            result.merge(stripe);
+        }
+        result
+    }
+}
+
+/// Reads back a file of stripes serialized one after another, combining them into a single stripe.
+pub fn read_stripes<R: BufRead>(mut reader: R) -> bincode::Result<Stripe> {
+    let mut stripes = Vec::new();
+    while !reader.fill_buf()?.is_empty() {
+        stripes.push(bincode::deserialize_from(&mut reader)?);
+    }
+    Ok(Stripe::concat_compatible(stripes))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::columnar::{parse_path, read_stripes, Stripe, ROW_ID_KEY};
+    use crate::datum::{Datum, InternalType};
+    use bit_vec::BitVec;
+    use serde_json::json;
+
+    #[test]
+    fn row_ids_survive_a_filter() {
+        let rows: Vec<Datum> = [
+            json!({"name": "a", "tags": ["x"]}),
+            json!({"name": "b", "tags": []}),
+            json!({"name": "c"}),
+            json!({"name": "d", "tags": ["y", "z"]}),
+            json!({"name": "e", "tags": [["nested"]]}),
+        ]
+        .into_iter()
+        .map(Datum::from)
+        .collect();
+        let mut stripe = Stripe::new().with_row_ids();
+        stripe.push_data(&rows);
+
+        let mask = BitVec::from_fn(rows.len(), |row| row != 0 && row != 3);
+        let filtered = stripe.apply_mask(&mask);
+        let mut row_ids = Vec::new();
+        for row in filtered.iter_rows() {
+            let Datum::Object(mut obj) = row else { panic!("expected an object") };
+            let row_id = match obj.remove(ROW_ID_KEY) {
+                Some(Datum::BigInt(row_id)) => row_id as usize,
+                other => panic!("expected a BigInt row id, got {other:?}"),
+            };
+            assert_eq!(Datum::Object(obj), rows[row_id]);
+            row_ids.push(row_id);
+        }
+        assert_eq!(row_ids, vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn apply_mask_keeps_bloom_filters() {
+        let key = parse_path("k").unwrap();
+        let value = |i: usize| Datum::String(format!("value {i}"));
+        let rows: Vec<Datum> = (0..40)
+            .map(|i| Datum::from(json!({"k": format!("value {i}")})))
+            .collect();
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        stripe.build_blooms();
+
+        let filtered = stripe.apply_mask(&BitVec::from_fn(40, |row| row % 4 == 0));
+        assert!((0..40).step_by(4).all(|i| filtered.might_contain(&key, &value(i))));
+        let false_positives = (0..40)
+            .filter(|i| i % 4 != 0 && filtered.might_contain(&key, &value(*i)))
+            .count();
+        assert!(false_positives <= 5, "{false_positives} false positives");
+    }
+
+    #[test]
+    fn split_keeps_bloom_filters() {
+        let key = parse_path("k").unwrap();
+        let value = |i: usize| Datum::String(format!("value {i}"));
+        let mut stripe = Stripe::new();
+        let rows: Vec<Datum> = (0..40)
+            .map(|i| Datum::from(json!({"k": format!("value {i}")})))
+            .collect();
+        stripe.push_data(&rows);
+        stripe.build_blooms();
+
+        for (part_idx, part) in stripe.split(10).iter().enumerate() {
+            let rows = part_idx * 10..part_idx * 10 + 10;
+            assert!(rows.clone().all(|i| part.might_contain(&key, &value(i))));
+            // Allowing for the odd false positive, values from the other parts are ruled out
+            let others = (0..40).filter(|i| !rows.contains(i));
+            let false_positives = others.filter(|i| part.might_contain(&key, &value(*i))).count();
+            assert!(false_positives <= 5, "{false_positives} false positives in part {part_idx}");
+        }
+    }
+
+    #[test]
+    fn split_at_keeps_bloom_filters() {
+        let key = parse_path("k").unwrap();
+        let value = |i: usize| Datum::String(format!("value {i}"));
+        let rows: Vec<Datum> = (0..40)
+            .map(|i| Datum::from(json!({"k": format!("value {i}")})))
+            .collect();
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        stripe.build_blooms();
+
+        let (first, second) = stripe.split_at(15);
+        for (part, rows) in [(first, 0..15), (second, 15..40)] {
+            assert!(rows.clone().all(|i| part.might_contain(&key, &value(i))));
+            let others = (0..40).filter(|i| !rows.contains(i));
+            let false_positives = others.filter(|i| part.might_contain(&key, &value(*i))).count();
+            assert!(false_positives <= 5, "{false_positives} false positives in rows {rows:?}");
+        }
+    }
+
+    #[test]
+    fn split_at_partitions_nested_rows() {
+        let rows: Vec<Datum> = (0..10)
+            .map(|i| match i % 3 {
+                0 => json!({"id": i, "items": [{"n": i}, {"n": i, "tags": ["a", "b"]}]}),
+                1 => json!({"id": i, "items": []}),
+                _ => json!({"id": i, "grid": [[i], [], [i, i]]}),
+            })
+            .map(Datum::from)
+            .collect();
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+
+        let (head, tail) = stripe.split_at(4);
+        assert_eq!((head.len(), tail.len()), (4, 6));
+        assert_eq!(head.to_data(), rows[..4]);
+        assert_eq!(tail.to_data(), rows[4..]);
+        assert_eq!(tail.get_row(0), rows[4]);
+    }
+
+    #[test]
+    fn stripes_read_back_widen_drifting_types() {
+        let mut bytes = Vec::new();
+        let mut stripes = Vec::new();
+        for rows in [vec![json!({"n": 1}), json!({"n": 2})], vec![json!({"n": 1.5}), json!({})]] {
+            let mut stripe = Stripe::new();
+            stripe.push_data(&rows.into_iter().map(Datum::from).collect::<Vec<_>>());
+            bincode::serialize_into(&mut bytes, &stripe).unwrap();
+            stripes.push(stripe);
+        }
+        let n = parse_path("n").unwrap();
+        assert_eq!(
+            Stripe::unified_schema(&stripes),
+            [(vec![], InternalType::Object), (n.clone(), InternalType::Float)]
+        );
+
+        let stripe = read_stripes(bytes.as_slice()).unwrap();
+        let schema = Stripe::unified_schema(std::slice::from_ref(&stripe));
+        assert_eq!(schema, Stripe::unified_schema(&stripes));
+        let expected = [json!({"n": 1.0}), json!({"n": 2.0}), json!({"n": 1.5}), json!({})];
+        assert_eq!(stripe.to_data(), expected.map(Datum::from));
+    }
+
+    #[test]
+    fn merge_takes_the_other_stripes_rows() {
+        let stripe = |rows: &[serde_json::Value]| {
+            let mut stripe = Stripe::new();
+            stripe.push_data(&rows.iter().cloned().map(Datum::from).collect::<Vec<_>>());
+            stripe
+        };
+        // Columns only the other stripe has are moved across, including nested ones
+        let first = [json!({"a": 1, "l": [1]})];
+        let second = [json!({"b": "x", "m": [{"k": [true]}]}), json!({"a": 2})];
+        let mut merged = stripe(&first);
+        merged.merge(stripe(&second));
+        let expected: Vec<Datum> = first.into_iter().chain(second).map(Datum::from).collect();
+        assert_eq!(merged.to_data(), expected);
+
+        // Integers meeting floats widen to floats
+        let mut merged = stripe(&[json!({"n": 1})]);
+        merged.merge(stripe(&[json!({"n": 2.5})]));
+        let n = parse_path("n").unwrap();
+        assert_eq!(merged.get_column(&n).unwrap().data.type_for(), InternalType::Float);
+        assert_eq!(merged.to_data(), [json!({"n": 1.0}), json!({"n": 2.5})].map(Datum::from));
+    }
+
+    #[test]
+    fn strings_merged_with_floats_become_a_union() {
+        let strings = [json!({"v": "a"}), json!({"v": null}), json!({"v": "1.5"})].map(Datum::from);
+        let floats = [json!({"v": 1.5}), json!({"v": 2.0}), json!({})].map(Datum::from);
+        let stripe = |rows: &[Datum]| {
+            let mut stripe = Stripe::new();
+            stripe.push_data(rows);
+            stripe
+        };
+        let mut merged = stripe(&strings);
+        merged.merge(stripe(&floats));
+        let v = parse_path("v").unwrap();
+        assert_eq!(merged.get_column(&v).unwrap().data.type_for(), InternalType::Union);
+        // Each value keeps its own type, so "1.5" and 1.5 stay apart and 2.0 stays a float
+        assert_eq!(merged.to_data(), [strings, floats].concat());
+    }
+}
@@ -0,0 +1,66 @@
+use crate::columnar::column::Slot;
+use crate::columnar::{path_depth, path_to_string, Stripe};
+use crate::datum::{Datum, InternalType};
+use std::error::Error;
+use std::io::Write;
+
+impl Stripe {
+    /// Writes the stripe out as csv, with a header row of column paths (see `path_to_string`)
+    /// followed by a row per record. Nested objects are flattened into a column per field, and
+    /// nulls and missing values are left as empty cells.
+    /// Arrays can't be represented so error out before anything is written, as do union columns
+    /// holding objects or arrays.
+    pub fn to_csv<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        let mut columns = Vec::new();
+        for (path, column) in &self.columns {
+            let nested = match column.data.type_for() {
+                InternalType::Object => continue,
+                InternalType::Array => true,
+                InternalType::Union => (0..column.null_map.len())
+                    .any(|idx| matches!(column.slot(idx), Slot::Object(_) | Slot::Array(_))),
+                _ => path_depth(path) > 1,
+            };
+            if nested {
+                let path = path_to_string(path);
+                return Err(format!("can't write nested values as csv, found {path}").into());
+            }
+            columns.push((path, column));
+        }
+
+        let header = columns
+            .iter()
+            .map(|(path, _)| path_to_string(path))
+            .collect();
+        write_record(&mut writer, header)?;
+        for row in 0..self.count {
+            let mut record = Vec::with_capacity(columns.len());
+            for (_, column) in &columns {
+                let cell = match column.find(&[row]).map(|idx| column.slot(idx)) {
+                    None | Some(Slot::Value(Datum::Null)) => String::new(),
+                    Some(Slot::Value(Datum::String(s))) => s,
+                    Some(Slot::Value(datum)) => datum.to_string(),
+                    Some(Slot::Object(_) | Slot::Array(_)) => unreachable!("checked above"),
+                };
+                record.push(cell);
+            }
+            write_record(&mut writer, record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes a line of csv, quoting any cells that need it
+fn write_record<W: Write>(writer: &mut W, cells: Vec<String>) -> std::io::Result<()> {
+    for (idx, cell) in cells.iter().enumerate() {
+        if idx > 0 {
+            writer.write_all(b",")?;
+        }
+        if cell.contains([',', '"', '\n', '\r']) {
+            write!(writer, "\"{}\"", cell.replace('"', "\"\""))?;
+        } else {
+            writer.write_all(cell.as_bytes())?;
+        }
+    }
+    writer.write_all(b"\n")
+}
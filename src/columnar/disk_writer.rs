@@ -0,0 +1,710 @@
+use crate::columnar::column::{Column, ColumnData, Union};
+use crate::columnar::indexes::Indexes;
+use crate::columnar::{
+    null_map, path_depth, union_layout, Path, StringEncoding, Stripe, StripeLayout,
+};
+use crate::datum::{Datum, InternalType};
+use serde::ser::{Error, SerializeSeq, SerializeStruct, SerializeTupleVariant};
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Gives each writer in the process its own spill file names
+static NEXT_WRITER: AtomicUsize = AtomicUsize::new(0);
+
+/// Builds a stripe too large to hold in memory.
+/// Rows are shredded into a small in memory stripe, every `rows_per_chunk` rows each column is
+/// appended to its own spill file, so memory is bounded by the chunk size rather than the data.
+/// `finish` then writes out the same bytes as serializing the whole stripe would, streaming each
+/// column's values straight from its spill file a chunk at a time. The spill files are read
+/// through several times (about twice per field of the column) to do this.
+pub struct DiskStripeWriter {
+    spill_dir: PathBuf,
+    spill_prefix: String,
+    rows_per_chunk: usize,
+    chunk: Stripe,
+    /// Rows already spilled to disk
+    count: usize,
+    spill_files: BTreeMap<Path, PathBuf>,
+}
+
+impl DiskStripeWriter {
+    /// Spill files are created in `spill_dir` (which must exist), named so they won't collide with
+    /// other writers, and removed once the writer is finished or dropped.
+    pub fn new(spill_dir: impl Into<PathBuf>, rows_per_chunk: usize) -> Self {
+        let writer = NEXT_WRITER.fetch_add(1, Ordering::Relaxed);
+        DiskStripeWriter {
+            spill_dir: spill_dir.into(),
+            spill_prefix: format!("jsonc-{}-{writer}", std::process::id()),
+            rows_per_chunk: rows_per_chunk.max(1),
+            chunk: Stripe::new(),
+            count: 0,
+            spill_files: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_string_encoding(mut self, string_encoding: StringEncoding) -> Self {
+        self.chunk.string_encoding = string_encoding;
+        self
+    }
+
+    pub fn push_datum(&mut self, datum: &Datum) -> bincode::Result<()> {
+        self.chunk.push_datum(datum);
+        if self.chunk.count >= self.rows_per_chunk {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Appends each column of the in memory chunk to its spill file, along with the row the chunk
+    /// starts at.
+    fn spill(&mut self) -> bincode::Result<()> {
+        let mut next_chunk = self.chunk.empty_copy();
+        // Each column keeps its type from one chunk to the next, so values are up-cast the same
+        // way they would be in one big stripe
+        for (path, column) in &self.chunk.columns {
+            next_chunk.columns.insert(path.clone(), column.empty_like());
+        }
+        let chunk = std::mem::replace(&mut self.chunk, next_chunk);
+        for (path, column) in &chunk.columns {
+            if column.null_map.is_empty() {
+                continue;
+            }
+            let file = match self.spill_files.get(path) {
+                Some(file_path) => OpenOptions::new().append(true).open(file_path)?,
+                None => {
+                    let file_name = format!(
+                        "{}-column_{}.spill",
+                        self.spill_prefix,
+                        self.spill_files.len()
+                    );
+                    let file_path = self.spill_dir.join(file_name);
+                    let file = OpenOptions::new()
+                        .append(true)
+                        .create_new(true)
+                        .open(&file_path)?;
+                    self.spill_files.insert(path.clone(), file_path);
+                    file
+                }
+            };
+            let mut writer = BufWriter::new(file);
+            bincode::serialize_into(&mut writer, &(self.count as u32, column))?;
+            writer.flush()?;
+        }
+        self.count += chunk.count;
+        Ok(())
+    }
+
+    /// Writes out the finished stripe, readable with `bincode::deserialize_from` like any other.
+    pub fn finish<W: Write>(mut self, writer: W) -> bincode::Result<()> {
+        self.spill()?;
+        let string_encoding = self.chunk.string_encoding;
+        let columns = self
+            .spill_files
+            .iter()
+            .map(|(path, file_path)| {
+                let column = SpilledColumn::open(file_path, path_depth(path), string_encoding)?;
+                Ok((path, column))
+            })
+            .collect::<bincode::Result<Vec<_>>>()?;
+        let layout = StripeLayout {
+            columns,
+            count: self.count,
+            row_ids: self.chunk.row_ids,
+            string_encoding,
+            max_columns: self.chunk.max_columns,
+        };
+        let mut writer = BufWriter::new(writer);
+        bincode::serialize_into(&mut writer, &layout)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for DiskStripeWriter {
+    fn drop(&mut self) {
+        for file_path in self.spill_files.values() {
+            // Nothing useful can be done about a failure while dropping
+            let _ = fs::remove_file(file_path);
+        }
+    }
+}
+
+/// A column held in a spill file as a series of chunks.
+/// Each chunk is read back as a part, ie the chunk copied into a column of the final column's
+/// type with its rows offset to where they sit in the stripe. The finished column is written out
+/// by concatenating what each part holds for each field.
+struct SpilledColumn<'a> {
+    file_path: &'a PathBuf,
+    string_encoding: StringEncoding,
+    /// An empty column of the final column's type
+    shape: Column,
+}
+
+impl<'a> SpilledColumn<'a> {
+    fn open(
+        file_path: &'a PathBuf,
+        depth: usize,
+        string_encoding: StringEncoding,
+    ) -> bincode::Result<Self> {
+        let mut shape = Column::new(depth, string_encoding);
+        let mut str_bytes = 0;
+        let mut inexact_ints = false;
+        for_each_chunk(file_path, |_, chunk| {
+            shape.up_cast(chunk.data.type_for());
+            inexact_ints |= chunk.has_inexact_ints(0..chunk.null_map.len());
+            if let ColumnData::String(str_buf, _) | ColumnData::LengthPrefixedString(str_buf, _) =
+                &chunk.data
+            {
+                str_bytes += str_buf.len() as u64;
+            }
+            Ok(true)
+        })?;
+        // Each part has to end up the same type, so large integers can't be mixed into a float
+        if inexact_ints && matches!(shape.data, ColumnData::Float(_)) {
+            shape.up_cast(InternalType::Union);
+        }
+        // The whole column would have outgrown u32 offsets in memory too
+        if str_bytes > u32::MAX as u64 {
+            shape.data.switch_to_offsets();
+        }
+        Ok(SpilledColumn {
+            file_path,
+            string_encoding,
+            shape,
+        })
+    }
+
+    fn try_for_each_part<E: Error>(
+        &self,
+        mut f: impl FnMut(&Column) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut result = Ok(());
+        for_each_chunk(self.file_path, |row_offset, chunk| {
+            let mut part = self.shape.clone();
+            part.extend_from(&chunk, 0..chunk.null_map.len(), |row| row + row_offset);
+            result = f(&part);
+            // Stops reading at the first error, which is then returned below
+            Ok(result.is_ok())
+        })
+        .map_err(E::custom)?;
+        result
+    }
+}
+
+/// Reads back the chunks of a spill file, along with the row each starts at, until `f` returns
+/// false.
+fn for_each_chunk(
+    file_path: &PathBuf,
+    mut f: impl FnMut(u32, Column) -> bincode::Result<bool>,
+) -> bincode::Result<()> {
+    let mut reader = BufReader::new(File::open(file_path)?);
+    while !reader.fill_buf()?.is_empty() {
+        let (row_offset, chunk): (u32, Column) = bincode::deserialize_from(&mut reader)?;
+        if !f(row_offset, chunk)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+impl Serialize for SpilledColumn<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut column = serializer.serialize_struct("Column", 5)?;
+        column.serialize_field("indexes", &SpilledIndexes(self))?;
+        column.serialize_field("data", &SpilledData(self))?;
+        column.serialize_field("null_map", &SpilledNullMap(self))?;
+        column.serialize_field("string_encoding", &self.string_encoding)?;
+        column.serialize_field("bloom", &None::<()>)?;
+        column.end()
+    }
+}
+
+/// A sequence of the values each part contributes, `values` is called on every part twice, once
+/// to count them and once to write them.
+struct Concat<'a, F> {
+    column: &'a SpilledColumn<'a>,
+    values: F,
+}
+
+fn concat<'a, T, F>(column: &'a SpilledColumn<'a>, values: F) -> Concat<'a, F>
+where
+    F: Fn(&Column) -> Box<dyn Iterator<Item = T> + '_>,
+{
+    Concat { column, values }
+}
+
+impl<T: Serialize, F: Fn(&Column) -> Box<dyn Iterator<Item = T> + '_>> Serialize for Concat<'_, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut len = 0;
+        self.column.try_for_each_part(|part| {
+            len += (self.values)(part).count();
+            Ok::<_, S::Error>(())
+        })?;
+        let mut seq = serializer.serialize_seq(Some(len))?;
+        self.column.try_for_each_part(|part| {
+            (self.values)(part).try_for_each(|value| seq.serialize_element(&value))
+        })?;
+        seq.end()
+    }
+}
+
+/// String end offsets, `ends` gives each part's offsets from the start of the part's strings and
+/// they're shifted along by the strings of the parts before.
+struct Offsets<'a, F> {
+    column: &'a SpilledColumn<'a>,
+    ends: F,
+}
+
+fn offsets<'a, F>(column: &'a SpilledColumn<'a>, ends: F) -> Offsets<'a, F>
+where
+    F: Fn(&Column) -> Box<dyn Iterator<Item = usize> + '_>,
+{
+    Offsets { column, ends }
+}
+
+impl<F: Fn(&Column) -> Box<dyn Iterator<Item = usize> + '_>> Serialize for Offsets<'_, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut len = 0;
+        self.column.try_for_each_part(|part| {
+            len += (self.ends)(part).count();
+            Ok::<_, S::Error>(())
+        })?;
+        let mut seq = serializer.serialize_seq(Some(len))?;
+        let mut start = 0;
+        self.column.try_for_each_part(|part| {
+            let mut end = start;
+            for part_end in (self.ends)(part) {
+                end = start + part_end;
+                seq.serialize_element(&end)?;
+            }
+            start = end;
+            Ok(())
+        })?;
+        seq.end()
+    }
+}
+
+/// Bits written out like a `BitVec`, ie `storage` packed into `u32` blocks (lowest bit first)
+/// followed by `nbits`.
+struct Bits<'a, F> {
+    column: &'a SpilledColumn<'a>,
+    bits: F,
+}
+
+fn bits<'a, F>(column: &'a SpilledColumn<'a>, bits: F) -> Bits<'a, F>
+where
+    F: Fn(&Column) -> Box<dyn Iterator<Item = bool> + '_>,
+{
+    Bits { column, bits }
+}
+
+impl<F: Fn(&Column) -> Box<dyn Iterator<Item = bool> + '_>> Serialize for Bits<'_, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut nbits = 0;
+        self.column.try_for_each_part(|part| {
+            nbits += (self.bits)(part).count();
+            Ok::<_, S::Error>(())
+        })?;
+        let mut bit_vec = serializer.serialize_struct("BitVec", 2)?;
+        bit_vec.serialize_field("storage", &Blocks { bits: self, nbits })?;
+        bit_vec.serialize_field("nbits", &nbits)?;
+        bit_vec.end()
+    }
+}
+
+struct Blocks<'a, F> {
+    bits: &'a Bits<'a, F>,
+    nbits: usize,
+}
+
+impl<F: Fn(&Column) -> Box<dyn Iterator<Item = bool> + '_>> Serialize for Blocks<'_, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.nbits.div_ceil(u32::BITS as usize)))?;
+        let mut block = 0u32;
+        let mut idx = 0;
+        self.bits.column.try_for_each_part(|part| {
+            for bit in (self.bits.bits)(part) {
+                block |= (bit as u32) << (idx % u32::BITS);
+                idx += 1;
+                if idx % u32::BITS == 0 {
+                    seq.serialize_element(&block)?;
+                    block = 0;
+                }
+            }
+            Ok(())
+        })?;
+        if idx % u32::BITS != 0 {
+            seq.serialize_element(&block)?;
+        }
+        seq.end()
+    }
+}
+
+fn values<T: Copy>(values: &[T]) -> Box<dyn Iterator<Item = T> + '_> {
+    Box::new(values.iter().copied())
+}
+
+/// Every part shares the column's type, so other types can't turn up
+fn other_type() -> ! {
+    unreachable!("parts of a spilled column all have the same type")
+}
+
+struct SpilledIndexes<'a>(&'a SpilledColumn<'a>);
+
+impl Serialize for SpilledIndexes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let column = self.0;
+        // Like `Indexes::push`, each level stays a range for as long as the indexes run on from
+        // each other
+        let mut levels: Vec<Option<Range<u32>>> = vec![Some(0..0); column.shape.indexes().len()];
+        column.try_for_each_part(|part| {
+            for (level, indexes) in levels.iter_mut().zip(part.indexes()) {
+                *level = match (level.take(), indexes) {
+                    (Some(range), Indexes::Sequential(next)) if range.is_empty() => {
+                        Some(next.clone())
+                    }
+                    (Some(range), Indexes::Sequential(next)) if next.is_empty() => Some(range),
+                    (Some(range), Indexes::Sequential(next)) if range.end == next.start => {
+                        Some(range.start..next.end)
+                    }
+                    _ => None,
+                };
+            }
+            Ok::<_, S::Error>(())
+        })?;
+
+        let mut seq = serializer.serialize_seq(Some(levels.len()))?;
+        for (level, range) in levels.into_iter().enumerate() {
+            match range {
+                Some(range) => seq.serialize_element(&Indexes::Sequential(range))?,
+                None => seq.serialize_element(&DenseIndexes { column, level })?,
+            }
+        }
+        seq.end()
+    }
+}
+
+struct DenseIndexes<'a> {
+    column: &'a SpilledColumn<'a>,
+    level: usize,
+}
+
+impl Serialize for DenseIndexes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let indexes = concat(self.column, |part| {
+            Box::new(part.indexes()[self.level].iter())
+        });
+        serializer.serialize_newtype_variant("Indexes", 0, "Dense", &indexes)
+    }
+}
+
+struct SpilledNullMap<'a>(&'a SpilledColumn<'a>);
+
+impl Serialize for SpilledNullMap<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let column = self.0;
+        let (mut len, mut nulls) = (0, 0);
+        column.try_for_each_part(|part| {
+            len += part.null_map.len();
+            nulls += part.null_map.iter().filter(|null| *null).count();
+            Ok::<_, S::Error>(())
+        })?;
+        let mask = bits(column, |part| Box::new(part.null_map.iter()));
+        null_map::serialize_mask(len, nulls, &mask, serializer)
+    }
+}
+
+struct SpilledData<'a>(&'a SpilledColumn<'a>);
+
+impl Serialize for SpilledData<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let column = self.0;
+        match &column.shape.data {
+            ColumnData::Null => serializer.serialize_unit_variant("ColumnData", 0, "Null"),
+            ColumnData::TinyInt(_) => {
+                let data = concat(column, |part| match &part.data {
+                    ColumnData::TinyInt(vec) => values(vec),
+                    _ => other_type(),
+                });
+                serializer.serialize_newtype_variant("ColumnData", 1, "TinyInt", &data)
+            }
+            ColumnData::SmallInt(_) => {
+                let data = concat(column, |part| match &part.data {
+                    ColumnData::SmallInt(vec) => values(vec),
+                    _ => other_type(),
+                });
+                serializer.serialize_newtype_variant("ColumnData", 2, "SmallInt", &data)
+            }
+            ColumnData::Int(_) => {
+                let data = concat(column, |part| match &part.data {
+                    ColumnData::Int(vec) => values(vec),
+                    _ => other_type(),
+                });
+                serializer.serialize_newtype_variant("ColumnData", 3, "Int", &data)
+            }
+            ColumnData::BigInt(_) => {
+                let data = concat(column, |part| match &part.data {
+                    ColumnData::BigInt(vec) => values(vec),
+                    _ => other_type(),
+                });
+                serializer.serialize_newtype_variant("ColumnData", 4, "BigInt", &data)
+            }
+            ColumnData::Float(_) => {
+                let data = concat(column, |part| match &part.data {
+                    ColumnData::Float(vec) => values(vec),
+                    _ => other_type(),
+                });
+                serializer.serialize_newtype_variant("ColumnData", 5, "Float", &data)
+            }
+            ColumnData::Bool(_) => {
+                let data = bits(column, |part| match &part.data {
+                    ColumnData::Bool(vec) => Box::new(vec.iter()),
+                    _ => other_type(),
+                });
+                serializer.serialize_newtype_variant("ColumnData", 6, "Bool", &data)
+            }
+            // Strings are written a byte at a time, which bincode encodes just like a `str`
+            ColumnData::String(_, _) => {
+                let mut data = serializer.serialize_tuple_variant("ColumnData", 7, "String", 2)?;
+                data.serialize_field(&concat(column, |part| match &part.data {
+                    ColumnData::String(str_buf, _) => Box::new(str_buf.bytes()),
+                    _ => other_type(),
+                }))?;
+                data.serialize_field(&offsets(column, |part| match &part.data {
+                    ColumnData::String(_, offsets) => values(offsets),
+                    _ => other_type(),
+                }))?;
+                data.end()
+            }
+            ColumnData::LengthPrefixedString(_, _) => {
+                let mut data = serializer.serialize_tuple_variant(
+                    "ColumnData",
+                    8,
+                    "LengthPrefixedString",
+                    2,
+                )?;
+                data.serialize_field(&concat(column, |part| match &part.data {
+                    ColumnData::LengthPrefixedString(str_buf, _) => Box::new(str_buf.bytes()),
+                    _ => other_type(),
+                }))?;
+                data.serialize_field(&concat(column, |part| match &part.data {
+                    ColumnData::LengthPrefixedString(_, ends) => {
+                        Box::new(ends.iter().scan(0, |start, end| {
+                            let len = end - *start;
+                            *start = *end;
+                            Some(len)
+                        }))
+                    }
+                    _ => other_type(),
+                }))?;
+                data.end()
+            }
+            ColumnData::Object(_) => {
+                let data = concat(column, |part| match &part.data {
+                    ColumnData::Object(sizes) => values(sizes),
+                    _ => other_type(),
+                });
+                serializer.serialize_newtype_variant("ColumnData", 9, "Object", &data)
+            }
+            ColumnData::Array(_) => {
+                let data = concat(column, |part| match &part.data {
+                    ColumnData::Array(sizes) => values(sizes),
+                    _ => other_type(),
+                });
+                serializer.serialize_newtype_variant("ColumnData", 10, "Array", &data)
+            }
+            ColumnData::Union(_) => serializer.serialize_newtype_variant(
+                "ColumnData",
+                11,
+                "Union",
+                &SpilledUnion(column),
+            ),
+            ColumnData::Constant(_) | ColumnData::DictString { .. } => {
+                unreachable!("spilled columns are never compacted")
+            }
+        }
+    }
+}
+
+/// A union column written out like `union_layout` does
+struct SpilledUnion<'a>(&'a SpilledColumn<'a>);
+
+fn union_values(part: &Column) -> &[Union] {
+    match &part.data {
+        ColumnData::Union(values) => values,
+        _ => other_type(),
+    }
+}
+
+impl Serialize for SpilledUnion<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let column = self.0;
+        let mut layout = serializer.serialize_struct("UnionLayout", 7)?;
+        layout.serialize_field(
+            "tags",
+            &concat(column, |part| {
+                Box::new(union_values(part).iter().map(union_layout::tag))
+            }),
+        )?;
+        layout.serialize_field(
+            "floats",
+            &concat(column, |part| {
+                Box::new(union_values(part).iter().filter_map(|value| match value {
+                    Union::Float(f) => Some(*f),
+                    _ => None,
+                }))
+            }),
+        )?;
+        layout.serialize_field(
+            "ints",
+            &concat(column, |part| {
+                Box::new(union_values(part).iter().filter_map(|value| match value {
+                    Union::Int(i) => Some(*i),
+                    _ => None,
+                }))
+            }),
+        )?;
+        layout.serialize_field(
+            "bools",
+            &bits(column, |part| {
+                Box::new(union_values(part).iter().filter_map(|value| match value {
+                    Union::Bool(b) => Some(*b),
+                    _ => None,
+                }))
+            }),
+        )?;
+        layout.serialize_field(
+            "str_buf",
+            &concat(column, |part| {
+                Box::new(union_values(part).iter().flat_map(|value| match value {
+                    Union::String(s) => s.bytes(),
+                    _ => "".bytes(),
+                }))
+            }),
+        )?;
+        layout.serialize_field(
+            "str_offsets",
+            &offsets(column, |part| {
+                let mut end = 0;
+                Box::new(union_values(part).iter().filter_map(move |value| match value {
+                    Union::String(s) => {
+                        end += s.len();
+                        Some(end)
+                    }
+                    _ => None,
+                }))
+            }),
+        )?;
+        layout.serialize_field(
+            "sizes",
+            &concat(column, |part| {
+                Box::new(union_values(part).iter().filter_map(|value| match value {
+                    Union::Array(size) | Union::Object(size) => Some(*size),
+                    _ => None,
+                }))
+            }),
+        )?;
+        layout.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::columnar::{DiskStripeWriter, StringEncoding, Stripe};
+    use crate::datum::Datum;
+    use serde_json::{json, Value};
+    use std::fs;
+
+    fn row(i: i64) -> Value {
+        let mixed = match i % 6 {
+            0 => json!(i),
+            1 => json!(format!("s{i}")),
+            2 => json!(null),
+            3 => json!([i, "x"]),
+            4 => json!({"k": i % 2 == 0}),
+            _ => json!(i as f64 / 4.0),
+        };
+        let mut row = json!({
+            "id": i * i * i * 1000,
+            "float": i as f64 / 3.0,
+            "flag": i % 3 == 0,
+            "mixed": mixed,
+            "always_null": null,
+            "nested": (0..i % 4).map(|j| (0..j).collect::<Vec<_>>()).collect::<Vec<_>>(),
+        });
+        if i % 4 != 1 {
+            row["sometimes"] = json!(format!("{}", "é".repeat(i as usize % 5)));
+        }
+        if i >= 20 {
+            row["late"] = json!(i);
+        }
+        row["null_then_int"] = if i < 25 { json!(null) } else { json!(i) };
+        // A large integer and then a float in a later chunk, only exact as a union
+        if i <= 42 {
+            row["large_then_float"] = match i {
+                10 => json!(9007199254740993_i64),
+                42 => json!(1.5),
+                _ => json!(i),
+            };
+        }
+        row
+    }
+
+    #[test]
+    fn matches_an_in_memory_stripe() {
+        let spill_dir =
+            std::env::temp_dir().join(format!("jsonc-disk-writer-{}", std::process::id()));
+        fs::create_dir_all(&spill_dir).unwrap();
+        let rows: Vec<Datum> = (0..50).map(|i| Datum::from(row(i))).collect();
+
+        for string_encoding in [StringEncoding::Offsets, StringEncoding::LengthPrefixed] {
+            let mut stripe = Stripe::new().with_string_encoding(string_encoding);
+            stripe.push_data(&rows);
+            let expected = bincode::serialize(&stripe).unwrap();
+
+            for rows_per_chunk in [1, 3, 7, 50, 64] {
+                let mut writer = DiskStripeWriter::new(&spill_dir, rows_per_chunk)
+                    .with_string_encoding(string_encoding);
+                for row in &rows {
+                    writer.push_datum(row).unwrap();
+                }
+                let mut bytes = Vec::new();
+                writer.finish(&mut bytes).unwrap();
+                assert!(
+                    bytes == expected,
+                    "{string_encoding:?} with {rows_per_chunk} rows per chunk"
+                );
+
+                let read_back: Stripe = bincode::deserialize(&bytes).unwrap();
+                assert!(read_back.iter_rows().eq(stripe.iter_rows()));
+            }
+        }
+        assert_eq!(fs::read_dir(&spill_dir).unwrap().count(), 0);
+        fs::remove_dir(&spill_dir).unwrap();
+    }
+
+    #[test]
+    fn dropping_removes_spill_files() {
+        let spill_dir =
+            std::env::temp_dir().join(format!("jsonc-disk-writer-drop-{}", std::process::id()));
+        fs::create_dir_all(&spill_dir).unwrap();
+        let mut first = DiskStripeWriter::new(&spill_dir, 1);
+        let mut second = DiskStripeWriter::new(&spill_dir, 1);
+        for i in 0..3 {
+            first.push_datum(&Datum::from(row(i))).unwrap();
+            second.push_datum(&Datum::from(row(i))).unwrap();
+        }
+        let spilled = fs::read_dir(&spill_dir).unwrap().count();
+        assert!(spilled > 0);
+        drop(first);
+        assert_eq!(fs::read_dir(&spill_dir).unwrap().count(), spilled / 2);
+        drop(second);
+        assert_eq!(fs::read_dir(&spill_dir).unwrap().count(), 0);
+        fs::remove_dir(&spill_dir).unwrap();
+    }
+}
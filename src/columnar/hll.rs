@@ -0,0 +1,53 @@
+use crate::columnar::bloom::hash_scalar;
+use crate::datum::Datum;
+
+/// Bits of the hash used to pick a register, 2^12 registers gives a standard error of ~1.6%
+const PRECISION: u32 = 12;
+const REGISTERS: usize = 1 << PRECISION;
+
+/// A HyperLogLog sketch for estimating the number of distinct scalar values
+pub(super) struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    pub(super) fn new() -> Self {
+        HyperLogLog {
+            registers: vec![0; REGISTERS],
+        }
+    }
+
+    /// Records a value, nested values and nulls aren't counted
+    pub(super) fn insert(&mut self, datum: &Datum) {
+        if let Some(hash) = hash_scalar(datum) {
+            // FNV doesn't spread its bits well enough for the leading zero count to be meaningful
+            let hash = fmix64(hash);
+            let register = (hash >> (64 - PRECISION)) as usize;
+            let rank = ((hash << PRECISION) | (1 << (PRECISION - 1))).leading_zeros() as u8 + 1;
+            self.registers[register] = self.registers[register].max(rank);
+        }
+    }
+
+    pub(super) fn estimate(&self) -> u64 {
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        // Linear counting is more accurate while most registers are still empty
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+/// The finalizer from MurmurHash3, mixes every input bit into every output bit
+fn fmix64(mut hash: u64) -> u64 {
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// One level of a column's indexes, holding an index per slot.
+/// For fields present in every row the top level is just `0, 1, 2, ...`, so runs like that are
+/// stored as a range, only falling back to storing every index once the run is broken.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Indexes {
+    Dense(Vec<u32>),
+    /// Indexes counting up by one from the start of the range
+    Sequential(Range<u32>),
+}
+
+impl Default for Indexes {
+    fn default() -> Self {
+        Indexes::Sequential(0..0)
+    }
+}
+
+impl Indexes {
+    pub fn len(&self) -> usize {
+        match self {
+            Indexes::Dense(vec) => vec.len(),
+            Indexes::Sequential(range) => range.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The index of a slot
+    pub fn get(&self, idx: usize) -> u32 {
+        match self {
+            Indexes::Dense(vec) => vec[idx],
+            Indexes::Sequential(range) => {
+                assert!(idx < range.len(), "slot {idx} out of range");
+                range.start + idx as u32
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        let (range, dense) = match self {
+            Indexes::Dense(vec) => (0..0, vec.as_slice()),
+            Indexes::Sequential(range) => (range.clone(), &[][..]),
+        };
+        range.chain(dense.iter().copied())
+    }
+
+    /// Materializes the index of every slot
+    pub fn to_vec(&self) -> Vec<u32> {
+        self.iter().collect()
+    }
+
+    pub(super) fn push(&mut self, index: u32) {
+        match self {
+            Indexes::Sequential(range) if range.start == range.end => *range = index..index + 1,
+            Indexes::Sequential(range) if range.end == index => range.end += 1,
+            Indexes::Sequential(range) => {
+                let mut vec: Vec<u32> = range.clone().collect();
+                vec.push(index);
+                *self = Indexes::Dense(vec);
+            }
+            Indexes::Dense(vec) => vec.push(index),
+        }
+    }
+
+    /// How many indexes can be held before reallocating, unlimited for sequential indexes
+    pub fn capacity(&self) -> usize {
+        match self {
+            Indexes::Dense(vec) => vec.capacity(),
+            Indexes::Sequential(_) => usize::MAX,
+        }
+    }
+
+    /// Adds `offset` to every index
+    pub(super) fn shift(&mut self, offset: u32) {
+        match self {
+            Indexes::Dense(vec) => vec.iter_mut().for_each(|index| *index += offset),
+            Indexes::Sequential(range) if range.start == range.end => {}
+            Indexes::Sequential(range) => *range = range.start + offset..range.end + offset,
+        }
+    }
+
+    pub(super) fn reserve(&mut self, additional: usize) {
+        if let Indexes::Dense(vec) = self {
+            vec.reserve(additional);
+        }
+    }
+
+    pub(super) fn shrink_to_fit(&mut self) {
+        if let Indexes::Dense(vec) = self {
+            vec.shrink_to_fit();
+        }
+    }
+
+    /// Like `slice::partition_point`, the indexes must be sorted with respect to `pred`
+    pub(super) fn partition_point(&self, pred: impl Fn(u32) -> bool) -> usize {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if pred(self.get(mid)) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+}
+
+impl Extend<u32> for Indexes {
+    fn extend<T: IntoIterator<Item = u32>>(&mut self, iter: T) {
+        for index in iter {
+            self.push(index);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::columnar::column::Slot;
+use crate::columnar::{PathComponent, Stripe};
+use crate::datum::{Datum, DatumKey};
+use std::collections::{BTreeSet, HashMap};
+
+impl Stripe {
+    /// Hash joins this stripe against `right`, extending each row with the top level keys of the
+    /// matching right hand row (left join semantics).
+    /// Keys are compared by value, numbers compare equal regardless of their internal width.
+    /// Only scalar keys can match, null/missing keys never do, and if several right hand rows
+    /// share a key the first one wins.
+    /// Right hand keys that already exist on the left are dropped, and rows that don't find a
+    /// match have the right hand keys filled with null.
+    pub fn left_join(
+        &self,
+        right: &Stripe,
+        left_key: &[PathComponent],
+        right_key: &[PathComponent],
+    ) -> Stripe {
+        let mut lookup = HashMap::new();
+        for row in 0..right.count {
+            if let Some(key) = right.join_key(right_key, row) {
+                lookup.entry(key).or_insert(row);
+            }
+        }
+
+        let left_keys: BTreeSet<&PathComponent> = self
+            .columns
+            .keys()
+            .filter(|path| path.len() == 1)
+            .map(|path| &path[0])
+            .collect();
+        let added: Vec<_> = right
+            .columns
+            .iter()
+            .filter(|(path, _)| {
+                matches!(path.first(), Some(key @ PathComponent::Key(_)) if !left_keys.contains(key))
+            })
+            .collect();
+
+        let mut joined = self.empty_copy();
+        joined.columns = self.columns.clone();
+        joined.count = self.count;
+        for row in 0..self.count {
+            // We can only extend rows that are objects
+            let root_slot = match self.columns.get(&[][..]) {
+                Some(root) => match root.row_range(row).next() {
+                    Some(idx) if matches!(root.slot(idx), Slot::Object(_)) => idx,
+                    _ => continue,
+                },
+                None => continue,
+            };
+            let matched = self
+                .join_key(left_key, row)
+                .and_then(|key| lookup.get(&key).copied());
+
+            let mut keys_added = 0;
+            for (path, column) in &added {
+                let new_column = joined.column_entry(path);
+                match matched {
+                    Some(right_row) => {
+                        let slots = column.row_range(right_row);
+                        if path.len() == 1 && !slots.is_empty() {
+                            keys_added += 1;
+                        }
+                        new_column.extend_from(column, slots, |_| row as u32);
+                    }
+                    None if path.len() == 1 => {
+                        new_column.add_datum(&Datum::Null, &[row]);
+                        keys_added += 1;
+                    }
+                    None => {}
+                }
+            }
+
+            joined
+                .columns
+                .get_mut(&[][..])
+                .unwrap()
+                .grow_object(root_slot, keys_added);
+        }
+        joined
+    }
+
+    /// Reads the key to join on for a given row, if it's a usable scalar
+    fn join_key(&self, path: &[PathComponent], row: usize) -> Option<DatumKey> {
+        let column = self.columns.get(path)?;
+        let mut slots = column.row_range(row);
+        match (slots.next(), slots.next()) {
+            (Some(idx), None) => match column.slot(idx) {
+                Slot::Value(Datum::Null) | Slot::Value(Datum::Missing) => None,
+                // NaN never equals anything, even itself
+                Slot::Value(datum) if datum.as_f64().is_some_and(f64::is_nan) => None,
+                Slot::Value(datum) => Some(DatumKey(datum)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::columnar::{PathComponent, Stripe};
+    use crate::datum::Datum;
+    use serde_json::json;
+
+    fn stripe(rows: serde_json::Value) -> Stripe {
+        let mut stripe = Stripe::new();
+        for row in rows.as_array().unwrap() {
+            stripe.push_datum(&Datum::from(row.clone()));
+        }
+        stripe
+    }
+
+    fn key(name: &str) -> Vec<PathComponent> {
+        vec![PathComponent::Key(name.to_string())]
+    }
+
+    #[test]
+    fn enriches_rows_on_string_key() {
+        let orders = stripe(json!([
+            {"order": 1, "customer": "alice"},
+            {"order": 2, "customer": "bob"},
+            {"order": 3, "customer": "carol"},
+            {"order": 4},
+        ]));
+        let customers = stripe(json!([
+            {"name": "bob", "city": "Paris", "address": {"zip": "75001"}},
+            {"name": "alice", "city": "Oslo", "address": {"zip": "0150"}},
+            {"name": "alice", "city": "Duplicate"},
+        ]));
+
+        let joined = orders.left_join(&customers, &key("customer"), &key("name"));
+        assert_eq!(
+            joined.to_data(),
+            vec![
+                Datum::from(json!({"order": 1, "customer": "alice", "name": "alice", "city": "Oslo", "address": {"zip": "0150"}})),
+                Datum::from(json!({"order": 2, "customer": "bob", "name": "bob", "city": "Paris", "address": {"zip": "75001"}})),
+                Datum::from(json!({"order": 3, "customer": "carol", "name": null, "city": null, "address": null})),
+                Datum::from(json!({"order": 4, "name": null, "city": null, "address": null})),
+            ]
+        );
+    }
+
+    #[test]
+    fn large_integer_keys_match_exactly() {
+        let left = stripe(json!([{"id": 9007199254740993i64}]));
+        let right = stripe(json!([
+            {"id": 9007199254740992i64, "v": "wrong"},
+            {"id": 9007199254740993i64, "v": "right"},
+        ]));
+
+        let joined = left.left_join(&right, &key("id"), &key("id"));
+        assert_eq!(
+            joined.to_data(),
+            vec![Datum::from(json!({"id": 9007199254740993i64, "v": "right"}))]
+        );
+    }
+}
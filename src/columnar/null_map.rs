@@ -0,0 +1,44 @@
+//! Serialization for a column's null map.
+//! Most columns are either entirely present (required fields) or entirely null, so rather than a
+//! bit per slot those cases are written as just the number of slots. In memory the null map is
+//! always a full `BitVec` so scans don't need to care.
+use bit_vec::BitVec;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Serialize, Deserialize)]
+enum NullMap {
+    AllPresent(usize),
+    AllNull(usize),
+    Mask(BitVec),
+}
+
+pub(super) fn serialize<S: Serializer>(null_map: &BitVec, serializer: S) -> Result<S::Ok, S::Error> {
+    let nulls = null_map.blocks().map(|block| block.count_ones() as usize).sum();
+    // Only the borrowed mask is needed, so avoid cloning it into a `NullMap`
+    serialize_mask(null_map.len(), nulls, null_map, serializer)
+}
+
+/// Writes a null map of `len` slots, `nulls` of them null, using `mask` (which serializes like a
+/// `BitVec`) only when it's needed. `DiskStripeWriter` uses this to stream the mask in from disk.
+pub(super) fn serialize_mask<S: Serializer, M: Serialize>(
+    len: usize,
+    nulls: usize,
+    mask: &M,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    if nulls == 0 {
+        NullMap::AllPresent(len).serialize(serializer)
+    } else if nulls == len {
+        NullMap::AllNull(len).serialize(serializer)
+    } else {
+        serializer.serialize_newtype_variant("NullMap", 2, "Mask", mask)
+    }
+}
+
+pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BitVec, D::Error> {
+    Ok(match NullMap::deserialize(deserializer)? {
+        NullMap::AllPresent(len) => BitVec::from_elem(len, false),
+        NullMap::AllNull(len) => BitVec::from_elem(len, true),
+        NullMap::Mask(mask) => mask,
+    })
+}
@@ -0,0 +1,62 @@
+use crate::columnar::{stripe_to_arrow, Stripe};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use std::io::Write;
+
+/// Writes the stripe out as a parquet file holding a single row group.
+/// The columns are the same as produced by `stripe_to_arrow`, so nested objects are flattened
+/// into dotted columns and anything nested under an array is left out for now.
+pub fn stripe_to_parquet<W: Write + Send>(stripe: &Stripe, writer: W) -> Result<(), ParquetError> {
+    let batch = stripe_to_arrow(stripe);
+    let mut writer = ArrowWriter::try_new(writer, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stripe_to_parquet;
+    use crate::columnar::{stripe_to_arrow, Stripe};
+    use crate::datum::Datum;
+    use arrow_array::{Array, BooleanArray, Int16Array, RecordBatch};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::json;
+    use std::fs::{self, File};
+
+    #[test]
+    fn written_stripes_read_back() {
+        let rows = [
+            json!({"id": 1, "name": "a", "user": {"age": 300}, "score": 1.5, "tags": ["x"]}),
+            json!({"id": 2, "user": {"age": null}, "score": -2.0, "ok": true}),
+            json!({"id": 3, "name": null, "user": {}, "ok": null, "tags": []}),
+        ]
+        .map(Datum::from);
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+
+        let path =
+            std::env::temp_dir().join(format!("jsonc-parquet-{}.parquet", std::process::id()));
+        stripe_to_parquet(&stripe, File::create(&path).unwrap()).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let expected = stripe_to_arrow(&stripe);
+        let names = |batch: &RecordBatch| {
+            let schema = batch.schema();
+            schema.fields().iter().map(|field| field.name().clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(names(&expected), ["id", "name", "ok", "score", "user.age"]);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(names(&batches[0]), names(&expected));
+        assert_eq!(batches[0].columns(), expected.columns());
+        let ages = batches[0].column(4).as_any().downcast_ref::<Int16Array>().unwrap();
+        assert_eq!(ages, &Int16Array::from(vec![Some(300), None, None]));
+        let ok = batches[0].column(2).as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert_eq!(ok, &BooleanArray::from(vec![None, Some(true), None]));
+    }
+}
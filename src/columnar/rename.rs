@@ -0,0 +1,128 @@
+use crate::columnar::{path_to_string, Path, PathComponent, Stripe};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Error returned when renaming keys would merge distinct columns into the same path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyCollision {
+    pub path: Path,
+}
+
+impl Display for KeyCollision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "renaming keys would merge multiple columns into {}",
+            path_to_string(&self.path)
+        )
+    }
+}
+
+impl Error for KeyCollision {}
+
+impl Stripe {
+    /// Renames every object key in the stripe, at every level of nesting.
+    /// If two keys would end up with the same name the stripe is left untouched and the first
+    /// colliding path is returned.
+    pub fn transform_keys(&mut self, f: impl Fn(&str) -> String) -> Result<(), KeyCollision> {
+        let renamed: Vec<Path> = self
+            .columns
+            .keys()
+            .map(|path| {
+                path.iter()
+                    .map(|component| match component {
+                        PathComponent::Key(key) => PathComponent::Key(f(key)),
+                        PathComponent::Array => PathComponent::Array,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut columns = BTreeMap::new();
+        for (path, column) in renamed.iter().zip(self.columns.values()) {
+            if columns.insert(path, column).is_some() {
+                return Err(KeyCollision { path: path.clone() });
+            }
+        }
+
+        let old_columns = std::mem::take(&mut self.columns);
+        self.columns = renamed.into_iter().zip(old_columns.into_values()).collect();
+        Ok(())
+    }
+
+    /// Renames every key in the stripe from camelCase to snake_case
+    pub fn rename_to_snake_case(&mut self) -> Result<(), KeyCollision> {
+        self.transform_keys(to_snake_case)
+    }
+}
+
+/// Converts a camelCase (or PascalCase) key to snake_case, runs of capitals are treated as one
+/// word so `htmlURLPath` becomes `html_url_path`.
+pub fn to_snake_case(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    let mut out = String::with_capacity(key.len() + 4);
+    for (idx, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && idx > 0 {
+            let prev = chars[idx - 1];
+            let next_is_lower = chars.get(idx + 1).is_some_and(|next| next.is_lowercase());
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_is_lower)
+            {
+                out.push('_');
+            }
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_snake_case, KeyCollision};
+    use crate::columnar::{parse_path, path_to_string, Stripe};
+    use crate::datum::Datum;
+    use serde_json::json;
+
+    #[test]
+    fn keys_become_snake_case() {
+        assert_eq!(to_snake_case("htmlURLPath"), "html_url_path");
+        assert_eq!(to_snake_case("PascalCase"), "pascal_case");
+        assert_eq!(to_snake_case("v2Name"), "v2_name");
+        assert_eq!(to_snake_case("already_snake"), "already_snake");
+
+        let mut stripe = Stripe::new();
+        stripe.push_value(json!({"userInfo": {"firstName": "a", "tagList": [{"tagId": 1}]}}));
+        stripe.rename_to_snake_case().unwrap();
+        let paths: Vec<String> = stripe.columns().map(|(path, _)| path_to_string(path)).collect();
+        assert_eq!(
+            paths,
+            [
+                "",
+                "user_info",
+                "user_info.first_name",
+                "user_info.tag_list",
+                "user_info.tag_list.[]",
+                "user_info.tag_list.[].tag_id"
+            ]
+        );
+        assert_eq!(
+            stripe.to_data(),
+            [Datum::from(json!({"user_info": {"first_name": "a", "tag_list": [{"tag_id": 1}]}}))]
+        );
+    }
+
+    #[test]
+    fn colliding_keys_leave_the_stripe_untouched() {
+        let rows = [json!({"fooBar": 1, "nested": {"x": 2}}), json!({"foo_bar": "y"})];
+        let rows = rows.map(Datum::from);
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        let paths = |stripe: &Stripe| stripe.columns().map(|(path, _)| path.clone()).collect();
+        let before: Vec<_> = paths(&stripe);
+
+        let err = stripe.rename_to_snake_case().unwrap_err();
+        assert_eq!(err, KeyCollision { path: parse_path("foo_bar").unwrap() });
+        assert_eq!(paths(&stripe), before);
+        assert_eq!(stripe.to_data(), rows);
+    }
+}
@@ -0,0 +1,78 @@
+//! Standalone run length encoding helpers for vectors of indexes.
+//! Nothing in the stripe format uses these, `Indexes` stores runs of consecutive indexes as
+//! ranges instead, they're exposed for custom encodings and for debugging.
+
+/// Run length encodes a vector of indexes into `(value, run_length)` pairs.
+pub fn rle_encode(values: &[u32]) -> Vec<(u32, u32)> {
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for value in values {
+        match runs.last_mut() {
+            Some((last, len)) if last == value && *len < u32::MAX => *len += 1,
+            _ => runs.push((*value, 1)),
+        }
+    }
+    runs
+}
+
+/// Expands `(value, run_length)` pairs produced by `rle_encode` back out into the original indexes.
+pub fn rle_decode(runs: &[(u32, u32)]) -> Vec<u32> {
+    let mut values = Vec::with_capacity(runs.iter().map(|(_, len)| *len as usize).sum());
+    for (value, len) in runs {
+        values.extend(std::iter::repeat_n(*value, *len as usize));
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rle_decode, rle_encode};
+
+    /// Xorshift, enough to generate a spread of inputs without pulling in a property testing crate
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// Indexes with a mix of runs and changes, drawn from a small or the full range of values
+        fn indexes(&mut self) -> Vec<u32> {
+            let len = (self.next() % 200) as usize;
+            let small_values = self.next() & 1 == 0;
+            let mut values = Vec::with_capacity(len);
+            while values.len() < len {
+                let value = match small_values {
+                    true => (self.next() % 4) as u32,
+                    false => self.next() as u32,
+                };
+                let run = 1 + (self.next() % 10) as usize;
+                values.extend(std::iter::repeat_n(value, run.min(len - values.len())));
+            }
+            values
+        }
+    }
+
+    #[test]
+    fn decode_inverts_encode() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..1000 {
+            let values = rng.indexes();
+            let runs = rle_encode(&values);
+            assert_eq!(rle_decode(&runs), values);
+            // Runs are maximal, neighbours never share a value
+            assert!(runs.windows(2).all(|pair| pair[0].0 != pair[1].0));
+            assert!(runs.iter().all(|(_, len)| *len > 0));
+        }
+    }
+
+    #[test]
+    fn edge_cases() {
+        for values in [vec![], vec![0], vec![u32::MAX; 3], vec![0, u32::MAX, 0], vec![7; 1000]] {
+            assert_eq!(rle_decode(&rle_encode(&values)), values);
+        }
+        assert_eq!(rle_encode(&[1, 1, 2, 1]), vec![(1, 2), (2, 1), (1, 1)]);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::columnar::Stripe;
+use crate::datum::Datum;
+use std::io::{BufRead, Write};
+
+/// Iterates over the rows of a file of stripes serialized one after another, as written by
+/// `Stripe::write_split`.
+/// Each stripe is decoded whole when its first row is reached, bincode gives no way to read part
+/// of a column, so memory is bounded by the largest stripe in the file. A file holding a single
+/// big stripe is read all at once, it's `write_split`'s `rows_per_stripe` that sets the bound.
+/// Iteration stops after the first error.
+pub struct SplitRowReader<R> {
+    reader: R,
+    stripe: Stripe,
+    next_row: usize,
+    failed: bool,
+}
+
+impl<R: BufRead> SplitRowReader<R> {
+    pub fn new(reader: R) -> Self {
+        SplitRowReader {
+            reader,
+            stripe: Stripe::new(),
+            next_row: 0,
+            failed: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for SplitRowReader<R> {
+    type Item = bincode::Result<Datum>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next_row >= self.stripe.count {
+            if self.failed {
+                return None;
+            }
+            let next_stripe = match self.reader.fill_buf() {
+                Ok([]) => return None,
+                Ok(_) => bincode::deserialize_from(&mut self.reader),
+                Err(err) => Err(err.into()),
+            };
+            match next_stripe {
+                Ok(stripe) => {
+                    self.stripe = stripe;
+                    self.next_row = 0;
+                }
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        self.next_row += 1;
+        Some(Ok(self.stripe.get_row(self.next_row - 1)))
+    }
+}
+
+impl Stripe {
+    /// Writes the stripe out as a series of stripes of at most `rows_per_stripe` rows (see
+    /// `split`), so it can be read back a stripe at a time with `SplitRowReader`, or all at once
+    /// with `read_stripes`.
+    pub fn write_split<W: Write>(self, mut writer: W, rows_per_stripe: usize) -> bincode::Result<()> {
+        for stripe in self.split(rows_per_stripe) {
+            bincode::serialize_into(&mut writer, &stripe)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SplitRowReader;
+    use crate::columnar::Stripe;
+    use crate::datum::Datum;
+    use serde_json::{json, Value};
+    use std::io::{BufRead, Read};
+
+    /// Tracks how far into the input has been read
+    struct Tracked<'a> {
+        input: &'a [u8],
+        consumed: usize,
+    }
+
+    impl Read for Tracked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = self.input.read(buf)?;
+            self.consumed += len;
+            Ok(len)
+        }
+    }
+
+    impl BufRead for Tracked<'_> {
+        fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+            Ok(self.input)
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.input.consume(amt);
+            self.consumed += amt;
+        }
+    }
+
+    #[test]
+    fn decodes_a_stripe_at_a_time() {
+        let rows: Vec<Datum> = (0..1000)
+            .map(|i| Datum::from(json!({"id": i, "name": format!("row {i}"), "tags": [i % 3]})))
+            .collect();
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        let mut file = Vec::new();
+        stripe.write_split(&mut file, 100).unwrap();
+
+        let mut reader = SplitRowReader::new(Tracked { input: &file, consumed: 0 });
+        for (idx, expected) in rows.iter().enumerate() {
+            // Compared as json as the column widens ids to a `SmallInt`
+            let row = reader.next().unwrap().unwrap();
+            assert_eq!(Value::from(&row), Value::from(expected));
+            // Only the stripe holding the current row has been read and decoded
+            assert_eq!(reader.stripe.len(), 100);
+            let stripes_read = idx / 100 + 1;
+            assert!(reader.reader.consumed < file.len() * (stripes_read + 1) / 10);
+        }
+        assert!(reader.next().is_none());
+        assert_eq!(reader.reader.consumed, file.len());
+    }
+}
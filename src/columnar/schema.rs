@@ -0,0 +1,386 @@
+use crate::columnar::column::Slot;
+use crate::columnar::{path_to_string, Path, PathComponent, Stripe};
+use crate::datum::{Datum, InternalType, JsonType};
+use crate::loader::read_rows;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::BufRead;
+use std::ops::Bound;
+
+/// The shape of the data in a stripe, one entry per column.
+/// Displays as a summary table of each path's type and null count.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    fields: BTreeMap<Path, FieldSchema>,
+    rows: usize,
+}
+
+/// What's allowed at a single path, along with some stats on the values seen there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSchema {
+    /// None if the column holds values of more than one type, in which case anything goes
+    pub json_type: Option<JsonType>,
+    pub nullable: bool,
+    /// For object keys, whether every object at the parent path has this key
+    pub required: bool,
+    /// The type the column is stored as
+    pub internal_type: InternalType,
+    /// Number of values stored at the path, including nulls. Paths nested under arrays count
+    /// every element.
+    pub values: usize,
+    pub nulls: usize,
+}
+
+/// A way in which a datum doesn't conform to a schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    TypeMismatch {
+        path: Path,
+        expected: Option<JsonType>,
+        found: JsonType,
+    },
+    MissingField {
+        path: Path,
+    },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::TypeMismatch { path, expected: Some(expected), found } => write!(
+                f,
+                "expected {:?} at {} but found {:?}",
+                expected,
+                path_to_string(path),
+                found
+            ),
+            Violation::TypeMismatch { path, expected: None, found } => {
+                write!(f, "unexpected {:?} at {}", found, path_to_string(path))
+            }
+            Violation::MissingField { path } => {
+                write!(f, "missing required field {}", path_to_string(path))
+            }
+        }
+    }
+}
+
+impl Display for Schema {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} rows", self.rows)?;
+        for (path, field) in &self.fields {
+            let path = if path.is_empty() { "<root>".to_string() } else { path_to_string(path) };
+            write!(f, "{path}: {:?}, {} values", field.internal_type, field.values)?;
+            if field.nulls > 0 {
+                write!(f, " ({} null)", field.nulls)?;
+            }
+            if field.required {
+                write!(f, ", required")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Schema {
+    /// Number of rows in the stripe the schema was taken from
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn field(&self, path: &[PathComponent]) -> Option<&FieldSchema> {
+        self.fields.get(path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &FieldSchema)> {
+        self.fields.iter()
+    }
+
+    fn child_keys<'a>(&'a self, path: &'a [PathComponent]) -> impl Iterator<Item = &'a str> {
+        // Descendants sort directly after their parent
+        self.fields
+            .range::<[PathComponent], _>((Bound::Excluded(path), Bound::Unbounded))
+            .map(|(child_path, _)| child_path)
+            .take_while(move |child_path| child_path.starts_with(path))
+            .filter_map(move |child_path| match &child_path[path.len()..] {
+                [PathComponent::Key(key)] => Some(key.as_str()),
+                _ => None,
+            })
+    }
+}
+
+impl Stripe {
+    /// Infers a schema from the data currently in the stripe, also summarising the type and number
+    /// of nulls at each path
+    pub fn schema(&self) -> Schema {
+        let fields = self
+            .columns
+            .iter()
+            .map(|(path, column)| {
+                let required = match path.split_last() {
+                    Some((PathComponent::Key(_), parent)) => {
+                        let parent = &self.columns[parent];
+                        let objects = (0..parent.null_map.len())
+                            .filter(|idx| matches!(parent.slot(*idx), Slot::Object(_)))
+                            .count();
+                        column.null_map.len() == objects
+                    }
+                    _ => false,
+                };
+                let nulls = column.null_map.iter().filter(|is_null| *is_null).count();
+                let field = FieldSchema {
+                    json_type: column.data.type_for().json_type(),
+                    nullable: nulls > 0,
+                    required,
+                    internal_type: column.data.type_for(),
+                    values: column.null_map.len(),
+                    nulls,
+                };
+                (path.clone(), field)
+            })
+            .collect();
+        Schema {
+            fields,
+            rows: self.count,
+        }
+    }
+
+    /// Every path across the stripes along with the type able to hold the values from all of them,
+    /// see `InternalType::common_type`. As when merging the stripes, integers too large to be held
+    /// exactly by a float need a union rather than widening to float.
+    pub fn unified_schema(stripes: &[Stripe]) -> Vec<(Path, InternalType)> {
+        let mut types: BTreeMap<&Path, (InternalType, bool)> = BTreeMap::new();
+        for stripe in stripes {
+            for (path, column) in &stripe.columns {
+                let column_type = column.data.type_for();
+                let inexact = column.has_inexact_ints(0..column.null_map.len());
+                types
+                    .entry(path)
+                    .and_modify(|(t, any_inexact)| {
+                        *t = t.common_type(column_type);
+                        *any_inexact |= inexact;
+                    })
+                    .or_insert((column_type, inexact));
+            }
+        }
+        types
+            .into_iter()
+            .map(|(path, (t, inexact))| match t {
+                InternalType::Float if inexact => (path.clone(), InternalType::Union),
+                t => (path.clone(), t),
+            })
+            .collect()
+    }
+}
+
+/// Infers the paths and types a stripe would end up with from just the first `sample_rows` rows of
+/// some json, much cheaper than building the stripe when exploring a file.
+/// Rows are read just as `load_json_reader` reads them, so comments, trailing commas and a top level
+/// array of rows are all handled the same way.
+/// Types are merged as in `Stripe::unified_schema`, so paths holding conflicting types come out as
+/// `InternalType::Union`.
+pub fn infer_schema<R: BufRead>(
+    reader: R,
+    sample_rows: usize,
+) -> Result<Vec<(Path, InternalType)>, Box<dyn Error>> {
+    if sample_rows == 0 {
+        return Ok(Vec::new());
+    }
+    let mut types = BTreeMap::new();
+    let mut rows = 0;
+    read_rows(reader, None, None, |row| {
+        collect_types(&row, &mut Vec::new(), &mut types);
+        rows += 1;
+        rows < sample_rows
+    })?;
+    Ok(types.into_iter().collect())
+}
+
+fn collect_types(datum: &Datum, path: &mut Path, types: &mut BTreeMap<Path, InternalType>) {
+    if datum.is_missing() {
+        return;
+    }
+    let datum_type = datum.internal_type();
+    match types.get_mut(path.as_slice()) {
+        Some(t) => *t = t.common_type(datum_type),
+        None => {
+            types.insert(path.clone(), datum_type);
+        }
+    }
+    match datum {
+        Datum::Object(obj) => {
+            for (key, value) in obj {
+                path.push(PathComponent::Key(key.clone()));
+                collect_types(value, path, types);
+                path.pop();
+            }
+        }
+        Datum::Array(arr) => {
+            path.push(PathComponent::Array);
+            for datum in arr {
+                collect_types(datum, path, types);
+            }
+            path.pop();
+        }
+        _ => {}
+    }
+}
+
+/// Checks a datum (ie a row about to be pushed) against a schema, paths the schema doesn't know
+/// about are allowed.
+pub fn validate_datum(datum: &Datum, schema: &Schema) -> Result<(), Vec<Violation>> {
+    let mut violations = Vec::new();
+    validate_at_path(datum, schema, &mut Vec::new(), &mut violations);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+fn validate_at_path(datum: &Datum, schema: &Schema, path: &mut Path, violations: &mut Vec<Violation>) {
+    let field = match schema.field(path) {
+        Some(field) if !datum.is_missing() => field,
+        _ => return,
+    };
+    let found = datum.json_type();
+    let matches = match (found, field.json_type) {
+        (JsonType::Null, _) => field.nullable,
+        (_, None) => true,
+        (found, Some(expected)) => found == expected,
+    };
+    if !matches {
+        violations.push(Violation::TypeMismatch {
+            path: path.clone(),
+            expected: field.json_type,
+            found,
+        });
+        return;
+    }
+
+    match datum {
+        Datum::Object(obj) => {
+            for (key, value) in datum.as_sorted_entries() {
+                path.push(PathComponent::Key(key.clone()));
+                validate_at_path(value, schema, path, violations);
+                path.pop();
+            }
+            for key in schema.child_keys(path) {
+                let present = obj.get(key).is_some_and(|value| !value.is_missing());
+                let child_path = [path.as_slice(), &[PathComponent::Key(key.to_string())]].concat();
+                if !present && schema.fields[&child_path].required {
+                    violations.push(Violation::MissingField { path: child_path });
+                }
+            }
+        }
+        Datum::Array(arr) => {
+            path.push(PathComponent::Array);
+            for datum in arr {
+                validate_at_path(datum, schema, path, violations);
+            }
+            path.pop();
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::columnar::{infer_schema, parse_path, validate_datum, Path, Stripe, Violation};
+    use crate::datum::{Datum, InternalType, JsonType};
+    use crate::loader::load_json_reader;
+    use serde_json::json;
+
+    fn loaded_schema(input: &str) -> Vec<(Path, InternalType)> {
+        let mut stripe = Stripe::new();
+        stripe.push_data(&load_json_reader(input.as_bytes()).unwrap());
+        Stripe::unified_schema(&[stripe])
+    }
+
+    #[test]
+    fn infers_what_the_loader_loads() {
+        let jsonc = concat!(
+            "// users\n",
+            "{\"id\": 1, /* inline */ \"tags\": [\"a\",],}\n",
+            "\n",
+            "{\"id\": \"x\", \"url\": \"http://x\"}\n",
+        );
+        let array = "[\n  {\"id\": 1.5},\n  {\"nested\": {\"ok\": true}},\n]\n";
+        for input in [jsonc, array] {
+            assert_eq!(infer_schema(input.as_bytes(), 10).unwrap(), loaded_schema(input));
+        }
+
+        let sampled = infer_schema(array.as_bytes(), 1).unwrap();
+        assert_eq!(sampled, loaded_schema("{\"id\": 1.5}"));
+        assert!(infer_schema(jsonc.as_bytes(), 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rows_are_validated_against_the_schema() {
+        let mut stripe = Stripe::new();
+        stripe.push_value(json!({"id": 1, "name": "a", "tags": ["x"]}));
+        stripe.push_value(json!({"id": 2, "name": null, "tags": []}));
+        let schema = stripe.schema();
+        let validate = |row| validate_datum(&Datum::from(row), &schema);
+
+        // Nulls are fine where they've been seen before, as are paths the schema doesn't know
+        assert_eq!(validate(json!({"id": 3, "name": null, "tags": ["y"], "new": true})), Ok(()));
+        assert_eq!(
+            validate(json!({"id": 3, "name": "b", "tags": [1]})),
+            Err(vec![Violation::TypeMismatch {
+                path: parse_path("tags.[]").unwrap(),
+                expected: Some(JsonType::String),
+                found: JsonType::Number,
+            }])
+        );
+        assert_eq!(
+            validate(json!({"id": null, "name": "b"})),
+            Err(vec![
+                Violation::TypeMismatch {
+                    path: parse_path("id").unwrap(),
+                    expected: Some(JsonType::Number),
+                    found: JsonType::Null,
+                },
+                Violation::MissingField { path: parse_path("tags").unwrap() },
+            ])
+        );
+    }
+
+    #[test]
+    fn unified_schema_widens_across_stripes() {
+        let stripes = [
+            json!({"n": 1, "id": 1, "tags": ["a"]}),
+            json!({"n": 300, "id": null, "tags": [2]}),
+            json!({"n": 1.5, "id": "x"}),
+        ]
+        .map(|row| {
+            let mut stripe = Stripe::new();
+            stripe.push_value(row);
+            stripe
+        });
+        let path = |path| parse_path(path).unwrap();
+        assert_eq!(
+            Stripe::unified_schema(&stripes),
+            [
+                (vec![], InternalType::Object),
+                (path("id"), InternalType::Union),
+                (path("n"), InternalType::Float),
+                (path("tags"), InternalType::Array),
+                (path("tags.[]"), InternalType::Union),
+            ]
+        );
+        // Integers only widen as far as they need to, and nulls fit any type
+        assert_eq!(
+            Stripe::unified_schema(&stripes[..2]),
+            [
+                (vec![], InternalType::Object),
+                (path("id"), InternalType::TinyInt),
+                (path("n"), InternalType::SmallInt),
+                (path("tags"), InternalType::Array),
+                (path("tags.[]"), InternalType::Union),
+            ]
+        );
+        assert_eq!(Stripe::unified_schema(&[]), []);
+    }
+}
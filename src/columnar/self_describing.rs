@@ -0,0 +1,188 @@
+use crate::columnar::column::Column;
+use crate::columnar::{parse_path, path_to_string, StringEncoding, Stripe};
+use crate::datum::InternalType;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::{Read, Write};
+
+// Self describing layout:
+// magic bytes `JSONCSD1`
+// u64 (little endian) length of the header, followed by the header as json, see `StripeHeader`
+// then each column in header order, bincode encoded and taking up its header's `bytes`
+
+const MAGIC: &[u8; 8] = b"JSONCSD1";
+
+/// The json header of a self describing stripe, describing the stripe and each of its columns so
+/// it can be inspected without knowing its shape up front.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StripeHeader {
+    pub rows: usize,
+    pub row_ids: bool,
+    pub string_encoding: StringEncoding,
+    pub max_columns: Option<usize>,
+    pub columns: Vec<ColumnHeader>,
+}
+
+/// Describes a single column within a self describing stripe
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnHeader {
+    /// In the form produced by `path_to_string`
+    pub path: String,
+    pub column_type: InternalType,
+    /// How the values are laid out, one of `plain`, `length_prefixed`, `constant` or `dictionary`
+    pub encoding: String,
+    /// Number of values stored, including nulls
+    pub values: usize,
+    pub nulls: usize,
+    /// Size of the encoded column following the header
+    pub bytes: u64,
+}
+
+impl Stripe {
+    /// Writes the stripe with a json header describing its columns in front of the column data,
+    /// see `read_self_describing_header`. Unlike the plain bincode encoding the header can be read
+    /// by anything that understands json.
+    /// Non-finite floats are written as is, use `canonicalize_floats` first to swap them out.
+    pub fn write_self_describing<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        let mut columns = Vec::with_capacity(self.columns.len());
+        let mut bodies = Vec::with_capacity(self.columns.len());
+        for (path, column) in &self.columns {
+            let body = bincode::serialize(column)?;
+            columns.push(ColumnHeader {
+                path: path_to_string(path),
+                column_type: column.data.type_for(),
+                encoding: column.data.encoding_name().to_string(),
+                values: column.null_map.len(),
+                nulls: column.null_map.iter().filter(|is_null| *is_null).count(),
+                bytes: body.len() as u64,
+            });
+            bodies.push(body);
+        }
+        let header = StripeHeader {
+            rows: self.count,
+            row_ids: self.row_ids,
+            string_encoding: self.string_encoding,
+            max_columns: self.max_columns,
+            columns,
+        };
+        let header = serde_json::to_vec(&header)?;
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&(header.len() as u64).to_le_bytes())?;
+        writer.write_all(&header)?;
+        for body in bodies {
+            writer.write_all(&body)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads back a stripe written by `write_self_describing`
+    pub fn read_self_describing<R: Read>(mut reader: R) -> Result<Stripe, Box<dyn Error>> {
+        let header = read_self_describing_header(&mut reader)?;
+        let mut stripe = Stripe {
+            count: header.rows,
+            row_ids: header.row_ids,
+            string_encoding: header.string_encoding,
+            max_columns: header.max_columns,
+            ..Stripe::new()
+        };
+        for column_header in header.columns {
+            let path = parse_path(&column_header.path)?;
+            let mut body = Vec::new();
+            (&mut reader).take(column_header.bytes).read_to_end(&mut body)?;
+            let column: Column = bincode::deserialize(&body)?;
+            stripe.columns.insert(path, column);
+        }
+        Ok(stripe)
+    }
+}
+
+/// Reads just the header of a stripe written by `Stripe::write_self_describing`, leaving the
+/// reader positioned at the start of the column data.
+pub fn read_self_describing_header<R: Read>(mut reader: R) -> Result<StripeHeader, Box<dyn Error>> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err("not a self describing stripe".into());
+    }
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let mut header = Vec::new();
+    (&mut reader).take(u64::from_le_bytes(len)).read_to_end(&mut header)?;
+    Ok(serde_json::from_slice(&header)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::columnar::{read_self_describing_header, Stripe};
+    use crate::datum::{Datum, InternalType};
+    use serde_json::json;
+
+    #[test]
+    fn round_trips_with_a_readable_header() {
+        let names = ["ann", "bob"];
+        let rows = (0..6)
+            .map(|i| json!({"user": {"name": names[i % 2]}, "tags": [i, null], "n": 7}))
+            .map(Datum::from)
+            .collect::<Vec<_>>();
+        let mut stripe = Stripe::new();
+        stripe.push_data(&rows);
+        stripe.compact();
+        let mut bytes = Vec::new();
+        stripe.write_self_describing(&mut bytes).unwrap();
+        // The header alone describes every column, and is followed by exactly their data
+        let mut reader = bytes.as_slice();
+        let header = read_self_describing_header(&mut reader).unwrap();
+        assert_eq!((header.rows, header.row_ids, header.max_columns), (6, false, None));
+        let columns: Vec<_> = header
+            .columns
+            .iter()
+            .map(|c| (c.path.as_str(), c.column_type, c.encoding.as_str(), c.values, c.nulls))
+            .collect();
+        assert_eq!(
+            columns,
+            [
+                ("", InternalType::Object, "constant", 6, 0),
+                ("n", InternalType::TinyInt, "constant", 6, 0),
+                ("tags", InternalType::Array, "constant", 6, 0),
+                ("tags.[]", InternalType::TinyInt, "plain", 12, 6),
+                ("user", InternalType::Object, "constant", 6, 0),
+                ("user.name", InternalType::String, "dictionary", 6, 0),
+            ]
+        );
+        let body_bytes: u64 = header.columns.iter().map(|c| c.bytes).sum();
+        assert_eq!(reader.len() as u64, body_bytes);
+
+        let read_back = Stripe::read_self_describing(bytes.as_slice()).unwrap();
+        assert_eq!(read_back.to_data(), rows);
+        let mut rewritten = Vec::new();
+        read_back.write_self_describing(&mut rewritten).unwrap();
+        assert_eq!(rewritten, bytes);
+
+        let err = read_self_describing_header(&b"JSONCSD0"[..]).unwrap_err();
+        assert_eq!(err.to_string(), "not a self describing stripe");
+    }
+
+    #[test]
+    fn canonicalized_floats_round_trip() {
+        let row = |f: Datum| Datum::Object([("f".to_string(), f)].into());
+        let floats = [1.5, f64::NAN, f64::INFINITY, f64::NEG_INFINITY];
+        let rows = floats.map(|f| row(Datum::Float(f)));
+        for (sentinel, replaced) in [(None, Datum::Null), (Some(-1.0), Datum::Float(-1.0))] {
+            let mut stripe = Stripe::new();
+            stripe.push_data(&rows);
+            stripe.canonicalize_floats(sentinel);
+            let mut bytes = Vec::new();
+            stripe.write_self_describing(&mut bytes).unwrap();
+
+            let header = read_self_describing_header(bytes.as_slice()).unwrap();
+            let nulls = if replaced.is_null() { 3 } else { 0 };
+            assert_eq!((header.columns[1].path.as_str(), header.columns[1].nulls), ("f", nulls));
+            let read_back = Stripe::read_self_describing(bytes.as_slice()).unwrap();
+            let mut expected = vec![row(Datum::Float(1.5))];
+            expected.extend([row(replaced.clone()), row(replaced.clone()), row(replaced)]);
+            assert_eq!(read_back.to_data(), expected);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::columnar::{Column, Slot};
+use crate::datum::{datum_ge, datum_gt, datum_le, datum_lt, Datum, InternalType};
+use std::cmp::Ordering;
+
+/// Summary statistics over the values in a column, see `Column::stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    pub nulls: usize,
+    pub non_null: usize,
+    /// Only tracked for number and string columns, None if there are no non-null values
+    pub min: Option<Datum>,
+    pub max: Option<Datum>,
+}
+
+/// A comparison against the values of a column, for ruling out columns (and so stripes) that
+/// can't hold any matching values, see `ColumnStats::might_match`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Eq(Datum),
+    Lt(Datum),
+    Le(Datum),
+    Gt(Datum),
+    Ge(Datum),
+}
+
+impl ColumnStats {
+    /// Returns false if no value described by these stats can match the predicate.
+    /// Nulls never match, and where the stats can't tell (eg no min and max are tracked, or the
+    /// predicate compares against another type) this errs on the side of returning true.
+    pub fn might_match(&self, predicate: &Predicate) -> bool {
+        if self.non_null == 0 {
+            return false;
+        }
+        let (Some(min), Some(max)) = (&self.min, &self.max) else {
+            return true;
+        };
+        // Comparisons give null when they can't be made, only a false rules the column out
+        let possible = |result: Datum| result != Datum::Bool(false);
+        match predicate {
+            Predicate::Eq(value) => {
+                possible(datum_le(min, value)) && possible(datum_ge(max, value))
+            }
+            Predicate::Lt(value) => possible(datum_lt(min, value)),
+            Predicate::Le(value) => possible(datum_le(min, value)),
+            Predicate::Gt(value) => possible(datum_gt(max, value)),
+            Predicate::Ge(value) => possible(datum_ge(max, value)),
+        }
+    }
+}
+
+impl Column {
+    /// Returns false if no value in the column can match the predicate, using the column's stats
+    /// along with its bloom filter for equality.
+    /// The stats are worked out on each call, when checking several predicates against the same
+    /// column hold onto `stats` and use `ColumnStats::might_match` instead.
+    pub fn might_match(&self, predicate: &Predicate) -> bool {
+        if let Predicate::Eq(value) = predicate {
+            if !self.might_contain(value) {
+                return false;
+            }
+        }
+        self.stats().might_match(predicate)
+    }
+
+    /// Computes the null counts and (for number and string columns) the min and max values.
+    /// For paths nested under arrays every element counts.
+    pub fn stats(&self) -> ColumnStats {
+        let nulls = self.null_map.iter().filter(|is_null| *is_null).count();
+        let compare: fn(&Datum, &Datum) -> Ordering = match self.data.type_for() {
+            InternalType::TinyInt
+            | InternalType::SmallInt
+            | InternalType::Int
+            | InternalType::BigInt => |a, b| a.as_i64().cmp(&b.as_i64()),
+            InternalType::Float => |a, b| a.as_f64().unwrap().total_cmp(&b.as_f64().unwrap()),
+            InternalType::String => |a, b| a.as_str().cmp(&b.as_str()),
+            _ => {
+                return ColumnStats {
+                    nulls,
+                    non_null: self.null_map.len() - nulls,
+                    min: None,
+                    max: None,
+                }
+            }
+        };
+
+        let mut min: Option<Datum> = None;
+        let mut max: Option<Datum> = None;
+        for idx in 0..self.null_map.len() {
+            if let Slot::Value(datum) = self.slot(idx) {
+                if datum.is_null() {
+                    continue;
+                }
+                if min.as_ref().is_none_or(|min| compare(&datum, min) == Ordering::Less) {
+                    min = Some(datum.clone());
+                }
+                if max.as_ref().is_none_or(|max| compare(&datum, max) == Ordering::Greater) {
+                    max = Some(datum);
+                }
+            }
+        }
+        ColumnStats {
+            nulls,
+            non_null: self.null_map.len() - nulls,
+            min,
+            max,
+        }
+    }
+}
@@ -0,0 +1,23 @@
+//! Serialization for length prefixed string columns.
+//! On disk we write the length of each string, but in memory the column holds where each string
+//! ends (still as a `u32`) so a slot can be found without adding up the lengths before it.
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub(super) fn serialize<S: Serializer>(ends: &[u32], serializer: S) -> Result<S::Ok, S::Error> {
+    let starts = std::iter::once(&0).chain(ends);
+    // Bad offsets wrap around here but are caught by validation when read back in
+    serializer.collect_seq(ends.iter().zip(starts).map(|(end, start)| end.wrapping_sub(*start)))
+}
+
+pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u32>, D::Error> {
+    let mut lengths = Vec::<u32>::deserialize(deserializer)?;
+    let mut end = 0u32;
+    for len in &mut lengths {
+        end = end
+            .checked_add(*len)
+            .ok_or_else(|| D::Error::custom("string lengths add up to more than u32::MAX"))?;
+        *len = end;
+    }
+    Ok(lengths)
+}
@@ -0,0 +1,126 @@
+//! Serialization for union columns.
+//! In memory a union column is a vec of enums, but on disk we store a type tag per value
+//! alongside type-homogeneous vectors (a struct of arrays), which avoids paying for the largest
+//! variant on every value and keeps the common case of a mostly single-typed column compact.
+use crate::columnar::column::Union;
+use bit_vec::BitVec;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+const NULL: u8 = 0;
+const FLOAT: u8 = 1;
+const BOOL: u8 = 2;
+const STRING: u8 = 3;
+const ARRAY: u8 = 4;
+const OBJECT: u8 = 5;
+const INT: u8 = 6;
+
+/// `DiskStripeWriter` writes this out field by field, so keep it in step when changing the layout.
+#[derive(Default, Serialize, Deserialize)]
+struct UnionLayout {
+    tags: Vec<u8>,
+    floats: Vec<f64>,
+    ints: Vec<i64>,
+    bools: BitVec,
+    str_buf: String,
+    str_offsets: Vec<usize>,
+    /// Sizes of both arrays and objects
+    sizes: Vec<usize>,
+}
+
+/// The tag a value is written with
+pub(super) fn tag(value: &Union) -> u8 {
+    match value {
+        Union::Null => NULL,
+        Union::Float(_) => FLOAT,
+        Union::Int(_) => INT,
+        Union::Bool(_) => BOOL,
+        Union::String(_) => STRING,
+        Union::Array(_) => ARRAY,
+        Union::Object(_) => OBJECT,
+    }
+}
+
+pub(super) fn serialize<S: Serializer>(values: &[Union], serializer: S) -> Result<S::Ok, S::Error> {
+    let mut layout = UnionLayout::default();
+    for value in values {
+        match value {
+            Union::Null => {}
+            Union::Float(f) => layout.floats.push(*f),
+            Union::Int(i) => layout.ints.push(*i),
+            Union::Bool(b) => layout.bools.push(*b),
+            Union::String(s) => {
+                layout.str_buf.push_str(s);
+                layout.str_offsets.push(layout.str_buf.len());
+            }
+            Union::Array(size) | Union::Object(size) => layout.sizes.push(*size),
+        }
+        layout.tags.push(tag(value));
+    }
+    layout.serialize(serializer)
+}
+
+pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Union>, D::Error> {
+    let layout = UnionLayout::deserialize(deserializer)?;
+    let mut floats = layout.floats.into_iter();
+    let mut ints = layout.ints.into_iter();
+    let mut bools = layout.bools.iter();
+    let mut str_offsets = layout.str_offsets.into_iter();
+    let mut sizes = layout.sizes.into_iter();
+    let mut str_start = 0;
+
+    let truncated = || D::Error::custom("union column truncated");
+    let mut values = Vec::with_capacity(layout.tags.len());
+    for tag in layout.tags {
+        let value = match tag {
+            NULL => Union::Null,
+            FLOAT => Union::Float(floats.next().ok_or_else(truncated)?),
+            INT => Union::Int(ints.next().ok_or_else(truncated)?),
+            BOOL => Union::Bool(bools.next().ok_or_else(truncated)?),
+            STRING => {
+                let end = str_offsets.next().ok_or_else(truncated)?;
+                let s = layout
+                    .str_buf
+                    .get(str_start..end)
+                    .ok_or_else(|| D::Error::custom("invalid union string offset"))?;
+                str_start = end;
+                Union::String(s.to_string())
+            }
+            ARRAY => Union::Array(sizes.next().ok_or_else(truncated)?),
+            OBJECT => Union::Object(sizes.next().ok_or_else(truncated)?),
+            tag => return Err(D::Error::custom(format!("unknown union tag {tag}"))),
+        };
+        values.push(value);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::columnar::column::Union;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Values(#[serde(with = "super")] Vec<Union>);
+
+    #[test]
+    fn values_round_trip() {
+        let values = Values(vec![
+            Union::Int(i64::MAX),
+            Union::Null,
+            Union::String("héllo".to_string()),
+            Union::Float(1.5),
+            Union::Bool(true),
+            Union::String(String::new()),
+            Union::Array(3),
+            Union::Bool(false),
+            Union::Object(0),
+            Union::Int(-1),
+            Union::String("world".to_string()),
+        ]);
+        let bytes = bincode::serialize(&values).unwrap();
+        assert_eq!(bincode::deserialize::<Values>(&bytes).unwrap(), values);
+        let json = serde_json::to_string(&values).unwrap();
+        assert_eq!(serde_json::from_str::<Values>(&json).unwrap(), values);
+    }
+}
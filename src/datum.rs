@@ -1,7 +1,12 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter, Write};
+use std::hash::{Hash, Hasher};
 
 /// "row orientated" layout for json-like data, used as an intermediate while loading data etc.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub enum Datum {
     Null,
     /// Represents a missing value, ie the difference between
@@ -10,12 +15,24 @@ pub enum Datum {
     Float(f64),
     TinyInt(i8),
     SmallInt(i16),
+    Int(i32),
+    BigInt(i64),
     Bool(bool),
     String(String),
+    /// A string sharing its allocation with other copies, see `load_json_interned`.
+    /// Compares equal to a `Datum::String` with the same contents.
+    SharedString(Arc<str>),
     Array(Vec<Datum>),
     Object(HashMap<String, Datum>),
 }
 
+/// A single step into a row datum, unlike `PathComponent`s array steps carry the element index.
+#[derive(Debug, Eq, PartialEq, Clone, Ord, PartialOrd, Hash)]
+pub enum Step {
+    Key(String),
+    Index(usize),
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum JsonType {
     Null,
@@ -27,13 +44,17 @@ pub enum JsonType {
     Object
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub(crate) enum InternalType {
+/// The types a column can be stored as, finer grained than `JsonType` as numbers come in several
+/// widths.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InternalType {
     Null,
     Missing,
     Float,
     TinyInt,
     SmallInt,
+    Int,
+    BigInt,
     Bool,
     String,
     Array,
@@ -49,8 +70,10 @@ impl Datum {
             Datum::Bool(_) => JsonType::Bool,
             Datum::Float(_) |
             Datum::TinyInt(_) |
-            Datum::SmallInt(_) => JsonType::Number,
-            Datum::String(_) => JsonType::String,
+            Datum::SmallInt(_) |
+            Datum::Int(_) |
+            Datum::BigInt(_) => JsonType::Number,
+            Datum::String(_) | Datum::SharedString(_) => JsonType::String,
             Datum::Array(_) => JsonType::Array,
             Datum::Object(_) => JsonType::Object
         }
@@ -64,12 +87,225 @@ impl Datum {
         matches!(self, Datum::Missing)
     }
 
+    /// True if there's no `Datum::Missing` anywhere within the datum, as is the case for anything
+    /// that came from json. Missing only makes sense as the result of a lookup.
+    pub fn is_loadable(&self) -> bool {
+        match self {
+            Datum::Missing => false,
+            Datum::Array(arr) => arr.iter().all(Datum::is_loadable),
+            Datum::Object(obj) => obj.values().all(Datum::is_loadable),
+            _ => true,
+        }
+    }
+
+    /// The number of scalar values (including nulls) within the datum, and how deeply nested it
+    /// is, a scalar having a depth of 1 and each enclosing object or array adding 1.
+    /// Useful for sizing buffers before shredding the datum into columns.
+    pub fn structure_stats(&self) -> (usize, usize) {
+        let children: Box<dyn Iterator<Item = &Datum>> = match self {
+            Datum::Missing => return (0, 0),
+            Datum::Array(arr) => Box::new(arr.iter()),
+            Datum::Object(obj) => Box::new(obj.values()),
+            _ => return (1, 1),
+        };
+        let (leaves, depth) = children
+            .map(Datum::structure_stats)
+            .fold((0, 0), |(leaves, depth), (l, d)| (leaves + l, depth.max(d)));
+        (leaves, depth + 1)
+    }
+
     pub fn as_f64(&self) -> Option<f64> {
         match self {
             Datum::Float(f) => Some(*f),
             Datum::SmallInt(i) => Some(*i as f64),
             Datum::TinyInt(i) => Some(*i as f64),
+            Datum::Int(i) => Some(*i as f64),
+            Datum::BigInt(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Datum::TinyInt(i) => Some(*i as i64),
+            Datum::SmallInt(i) => Some(*i as i64),
+            Datum::Int(i) => Some(*i as i64),
+            Datum::BigInt(i) => Some(*i),
+            // Only floats holding a whole number that fits
+            Datum::Float(f) if f.fract() == 0.0 && *f >= i64::MIN as f64 && *f < i64::MAX as f64 => {
+                Some(*f as i64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Datum::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Datum::String(s) => Some(s),
+            Datum::SharedString(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Looks up a key in an object, returns None for anything else
+    pub fn get(&self, key: &str) -> Option<&Datum> {
+        match self {
+            Datum::Object(obj) => obj.get(key),
+            _ => None,
+        }
+    }
+
+    /// Looks up a nested value, returning None if any step doesn't resolve
+    pub fn get_path(&self, path: &[Step]) -> Option<&Datum> {
+        path.iter().try_fold(self, |datum, step| match (datum, step) {
+            (Datum::Object(obj), Step::Key(key)) => obj.get(key),
+            (Datum::Array(arr), Step::Index(idx)) => arr.get(*idx),
             _ => None,
+        })
+    }
+
+    /// Looks up a nested value using a JSON pointer (RFC 6901) like `/foo/bar/0`, mirroring
+    /// `serde_json::Value::pointer`. Use `get_path` when the steps are already split out.
+    pub fn pointer(&self, pointer: &str) -> Option<&Datum> {
+        if pointer.is_empty() {
+            return Some(self);
+        }
+        pointer
+            .strip_prefix('/')?
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .try_fold(self, |datum, token| match datum {
+                Datum::Object(obj) => obj.get(&token),
+                // Leading zeros and signs aren't valid indexes
+                Datum::Array(arr) if token == "0" || !token.starts_with(['0', '+']) => {
+                    arr.get(token.parse::<usize>().ok()?)
+                }
+                _ => None,
+            })
+    }
+
+    /// Like `get_path` but allows the nested value to be modified in place
+    pub fn get_path_mut(&mut self, path: &[Step]) -> Option<&mut Datum> {
+        path.iter().try_fold(self, |datum, step| match (datum, step) {
+            (Datum::Object(obj), Step::Key(key)) => obj.get_mut(key),
+            (Datum::Array(arr), Step::Index(idx)) => arr.get_mut(*idx),
+            _ => None,
+        })
+    }
+
+    /// Drops the keys of an object that aren't in `keep`, in place so the object keeps its
+    /// capacity. With `recursive` the same keys are kept within any nested objects too, including
+    /// those inside arrays. Does nothing for non-objects unless `recursive`, in which case arrays
+    /// have their elements filtered.
+    pub fn filter_keys(&mut self, keep: &HashSet<String>, recursive: bool) {
+        match self {
+            Datum::Object(obj) => {
+                obj.retain(|key, _| keep.contains(key));
+                if recursive {
+                    for value in obj.values_mut() {
+                        value.filter_keys(keep, recursive);
+                    }
+                }
+            }
+            Datum::Array(arr) if recursive => {
+                for datum in arr {
+                    datum.filter_keys(keep, recursive);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The entries of an object in key order, giving a stable view over the underlying `HashMap`.
+    /// Returns nothing for non-objects.
+    pub fn as_sorted_entries(&self) -> Vec<(&String, &Datum)> {
+        match self {
+            Datum::Object(obj) => {
+                let mut entries: Vec<_> = obj.iter().collect();
+                entries.sort_unstable_by_key(|(k, _)| *k);
+                entries
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Serializes the datum as compact JSON, missing object values are skipped.
+    /// Objects are backed by a `HashMap` so have no order of their own, `sort_keys` emits
+    /// the keys of every object in lexical order giving a canonical output.
+    /// Whole floats keep their `.0` (`5.0` rather than `5`), otherwise they'd be loaded back in
+    /// as integers, changing the type of the column they end up in.
+    pub fn to_json(&self, sort_keys: bool) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out, sort_keys, None, 0);
+        out
+    }
+
+    /// Serializes the datum as multi-line JSON, with nested values indented by `indent` spaces per
+    /// level and object keys sorted, missing object values are skipped.
+    pub fn to_json_pretty(&self, indent: usize) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out, true, Some(indent), 0);
+        out
+    }
+
+    fn write_json(&self, out: &mut String, sort_keys: bool, indent: Option<usize>, level: usize) {
+        match self {
+            Datum::Null | Datum::Missing => out.push_str("null"),
+            Datum::Bool(b) => write!(out, "{b}").unwrap(),
+            Datum::TinyInt(i) => write!(out, "{i}").unwrap(),
+            Datum::SmallInt(i) => write!(out, "{i}").unwrap(),
+            Datum::Int(i) => write!(out, "{i}").unwrap(),
+            Datum::BigInt(i) => write!(out, "{i}").unwrap(),
+            // Serde takes care of the non-finite floats for us, and keeps the `.0` on whole floats
+            Datum::Float(f) => out.push_str(&serde_json::to_string(f).unwrap()),
+            Datum::String(_) | Datum::SharedString(_) => {
+                out.push_str(&serde_json::to_string(self.as_str().unwrap()).unwrap())
+            }
+            Datum::Array(arr) if arr.is_empty() => out.push_str("[]"),
+            Datum::Array(arr) => {
+                out.push('[');
+                for (idx, datum) in arr.iter().enumerate() {
+                    if idx > 0 {
+                        out.push(',');
+                    }
+                    write_newline(out, indent, level + 1);
+                    datum.write_json(out, sort_keys, indent, level + 1);
+                }
+                write_newline(out, indent, level);
+                out.push(']');
+            }
+            Datum::Object(obj) => {
+                let mut entries: Vec<_> = obj.iter().filter(|(_, v)| !v.is_missing()).collect();
+                if entries.is_empty() {
+                    out.push_str("{}");
+                    return;
+                }
+                if sort_keys {
+                    entries.sort_unstable_by_key(|(k, _)| *k);
+                }
+                out.push('{');
+                for (idx, (key, datum)) in entries.into_iter().enumerate() {
+                    if idx > 0 {
+                        out.push(',');
+                    }
+                    write_newline(out, indent, level + 1);
+                    out.push_str(&serde_json::to_string(key).unwrap());
+                    out.push(':');
+                    if indent.is_some() {
+                        out.push(' ');
+                    }
+                    datum.write_json(out, sort_keys, indent, level + 1);
+                }
+                write_newline(out, indent, level);
+                out.push('}');
+            }
         }
     }
 
@@ -79,11 +315,507 @@ impl Datum {
             Datum::Missing => InternalType::Missing,
             Datum::Float(_) => InternalType::Float,
             Datum::Bool(_) => InternalType::Bool,
-            Datum::String(_) => InternalType::String,
+            Datum::String(_) | Datum::SharedString(_) => InternalType::String,
             Datum::Array(_) => InternalType::Array,
             Datum::Object(_) => InternalType::Object,
             Datum::TinyInt(_) => InternalType::TinyInt,
             Datum::SmallInt(_) => InternalType::SmallInt,
+            Datum::Int(_) => InternalType::Int,
+            Datum::BigInt(_) => InternalType::BigInt,
+        }
+    }
+}
+
+impl InternalType {
+    /// The narrowest type both types can be widened to. Integers widen to the larger integer and
+    /// then to float, nulls fit anything, any other mix of types needs a union.
+    pub fn common_type(self, other: InternalType) -> InternalType {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (InternalType::Null | InternalType::Missing, t)
+            | (t, InternalType::Null | InternalType::Missing) => t,
+            (a, b) => match (a.numeric_rank(), b.numeric_rank()) {
+                (Some(rank_a), Some(rank_b)) => {
+                    if rank_a >= rank_b {
+                        a
+                    } else {
+                        b
+                    }
+                }
+                _ => InternalType::Union,
+            },
+        }
+    }
+
+    /// Position on the numeric widening ladder
+    fn numeric_rank(self) -> Option<u8> {
+        match self {
+            InternalType::TinyInt => Some(0),
+            InternalType::SmallInt => Some(1),
+            InternalType::Int => Some(2),
+            InternalType::BigInt => Some(3),
+            InternalType::Float => Some(4),
+            _ => None,
+        }
+    }
+
+    /// The json type values of this type take, None for unions as they can hold any type
+    pub(crate) fn json_type(self) -> Option<JsonType> {
+        match self {
+            InternalType::Null => Some(JsonType::Null),
+            InternalType::Missing => Some(JsonType::Missing),
+            InternalType::Float
+            | InternalType::TinyInt
+            | InternalType::SmallInt
+            | InternalType::Int
+            | InternalType::BigInt => Some(JsonType::Number),
+            InternalType::Bool => Some(JsonType::Bool),
+            InternalType::String => Some(JsonType::String),
+            InternalType::Array => Some(JsonType::Array),
+            InternalType::Object => Some(JsonType::Object),
+            InternalType::Union => None,
+        }
+    }
+}
+
+impl PartialEq for Datum {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Datum::Null, Datum::Null) | (Datum::Missing, Datum::Missing) => true,
+            (Datum::Float(a), Datum::Float(b)) => a == b,
+            (Datum::TinyInt(a), Datum::TinyInt(b)) => a == b,
+            (Datum::SmallInt(a), Datum::SmallInt(b)) => a == b,
+            (Datum::Int(a), Datum::Int(b)) => a == b,
+            (Datum::BigInt(a), Datum::BigInt(b)) => a == b,
+            (Datum::Bool(a), Datum::Bool(b)) => a == b,
+            (Datum::Array(a), Datum::Array(b)) => a == b,
+            (Datum::Object(a), Datum::Object(b)) => a == b,
+            // Shared or not, strings compare by their contents
+            _ => matches!((self.as_str(), other.as_str()), (Some(a), Some(b)) if a == b),
+        }
+    }
+}
+
+/// Wraps a datum so it can be used as a `HashMap`/`HashSet` key, eg for grouping rows.
+/// Compares by json value rather than by representation: numbers are equal regardless of width
+/// (`TinyInt(5)`, `SmallInt(5)` and `Float(5.0)` are all the same key), NaN equals NaN and -0.0
+/// equals 0.0, shared and unshared strings are equal and object key order doesn't matter.
+#[derive(Clone, Debug)]
+pub struct DatumKey(pub Datum);
+
+/// A number normalized so that equal values have equal representations
+#[derive(PartialEq, Eq, Hash)]
+enum NumberKey {
+    Int(i64),
+    Float(u64),
+}
+
+impl Datum {
+    /// A hash of the datum's json value that's stable across builds and platforms, so can be
+    /// persisted for deduplication or change detection. Values equal as `DatumKey`s hash the same.
+    ///
+    /// The hash is FNV-1a over a canonical encoding: each value is a tag byte followed by its
+    /// contents, numbers are written as an `i64` if they're integral (whatever their width) or
+    /// otherwise as the bits of an `f64` (with NaN and -0.0 normalized), strings, arrays and
+    /// objects are prefixed with their length and object entries are written in key order.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = StableHasher(0xcbf2_9ce4_8422_2325);
+        self.write_canonical(&mut hasher);
+        hasher.0
+    }
+
+    fn write_canonical(&self, hasher: &mut StableHasher) {
+        match self {
+            Datum::Null => hasher.write(&[0]),
+            Datum::Missing => hasher.write(&[1]),
+            Datum::Bool(b) => hasher.write(&[2, *b as u8]),
+            Datum::String(_) | Datum::SharedString(_) => {
+                hasher.write(&[3]);
+                hasher.write_str(self.as_str().unwrap());
+            }
+            Datum::Array(arr) => {
+                hasher.write(&[4]);
+                hasher.write(&(arr.len() as u64).to_le_bytes());
+                for datum in arr {
+                    datum.write_canonical(hasher);
+                }
+            }
+            Datum::Object(obj) => {
+                hasher.write(&[5]);
+                hasher.write(&(obj.len() as u64).to_le_bytes());
+                for (key, datum) in self.as_sorted_entries() {
+                    hasher.write_str(key);
+                    datum.write_canonical(hasher);
+                }
+            }
+            _ => match self.number_key() {
+                Some(NumberKey::Int(i)) => {
+                    hasher.write(&[6]);
+                    hasher.write(&i.to_le_bytes());
+                }
+                Some(NumberKey::Float(bits)) => {
+                    hasher.write(&[7]);
+                    hasher.write(&bits.to_le_bytes());
+                }
+                None => unreachable!(),
+            },
+        }
+    }
+
+    fn number_key(&self) -> Option<NumberKey> {
+        if let Some(i) = self.as_i64() {
+            return Some(NumberKey::Int(i));
+        }
+        let f = self.as_f64()?;
+        let f = if f.is_nan() { f64::NAN } else { f + 0.0 };
+        Some(NumberKey::Float(f.to_bits()))
+    }
+
+    fn key_eq(&self, other: &Datum) -> bool {
+        match (self, other) {
+            (Datum::Array(a), Datum::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.key_eq(b))
+            }
+            (Datum::Object(a), Datum::Object(b)) => {
+                a.len() == b.len()
+                    && a.iter().all(|(k, v)| b.get(k).is_some_and(|other| v.key_eq(other)))
+            }
+            _ => match (self.number_key(), other.number_key()) {
+                (Some(a), Some(b)) => a == b,
+                (None, None) => self == other,
+                _ => false,
+            },
+        }
+    }
+
+    fn key_hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(&self.json_type()).hash(state);
+        match self {
+            Datum::Bool(b) => b.hash(state),
+            Datum::String(_) | Datum::SharedString(_) => self.as_str().hash(state),
+            Datum::Array(arr) => {
+                arr.len().hash(state);
+                arr.iter().for_each(|datum| datum.key_hash(state));
+            }
+            Datum::Object(_) => {
+                for (key, datum) in self.as_sorted_entries() {
+                    key.hash(state);
+                    datum.key_hash(state);
+                }
+            }
+            _ => self.number_key().hash(state),
+        }
+    }
+}
+
+impl PartialEq for DatumKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.key_eq(&other.0)
+    }
+}
+
+impl Eq for DatumKey {}
+
+impl Hash for DatumKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.key_hash(state)
+    }
+}
+
+/// Writes the datum as compact JSON with sorted keys, see `Datum::to_json`.
+/// A top level `Datum::Missing` is written as null.
+impl Display for Datum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_json(true))
+    }
+}
+
+/// Builds an object from a sorted map, eg one written out in a test
+impl From<BTreeMap<String, Datum>> for Datum {
+    fn from(map: BTreeMap<String, Datum>) -> Self {
+        Datum::Object(map.into_iter().collect())
+    }
+}
+
+/// Takes the entries of an object in key order, handing back anything other than an object as
+/// the error.
+impl TryFrom<Datum> for BTreeMap<String, Datum> {
+    type Error = Datum;
+
+    fn try_from(datum: Datum) -> Result<Self, Self::Error> {
+        match datum {
+            Datum::Object(obj) => Ok(obj.into_iter().collect()),
+            other => Err(other),
+        }
+    }
+}
+
+/// Starts a new line at the given nesting level when pretty printing
+fn write_newline(out: &mut String, indent: Option<usize>, level: usize) {
+    if let Some(width) = indent {
+        out.push('\n');
+        out.extend(std::iter::repeat_n(' ', width * level));
+    }
+}
+
+/// FNV-1a, used for `Datum::content_hash` as unlike the std hashers its output is fixed
+struct StableHasher(u64);
+
+impl StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.write(&(s.len() as u64).to_le_bytes());
+        self.write(s.as_bytes());
+    }
+}
+
+// SQL-like comparisons for filtering, using three valued logic:
+// * Comparing with null (or missing) gives `Datum::Null`, ie unknown.
+// * Numbers compare by value whatever their width, so `TinyInt(1)` equals `Float(1.0)`.
+// * Strings compare lexically by bytes, and `false` sorts before `true`.
+// * Anything else, ie mixed types or arrays and objects, gives `Datum::Null` too.
+
+pub fn datum_eq(a: &Datum, b: &Datum) -> Datum {
+    compare_with(a, b, Ordering::is_eq)
+}
+
+pub fn datum_ne(a: &Datum, b: &Datum) -> Datum {
+    compare_with(a, b, Ordering::is_ne)
+}
+
+pub fn datum_lt(a: &Datum, b: &Datum) -> Datum {
+    compare_with(a, b, Ordering::is_lt)
+}
+
+pub fn datum_le(a: &Datum, b: &Datum) -> Datum {
+    compare_with(a, b, Ordering::is_le)
+}
+
+pub fn datum_gt(a: &Datum, b: &Datum) -> Datum {
+    compare_with(a, b, Ordering::is_gt)
+}
+
+pub fn datum_ge(a: &Datum, b: &Datum) -> Datum {
+    compare_with(a, b, Ordering::is_ge)
+}
+
+fn compare_with(a: &Datum, b: &Datum, test: fn(Ordering) -> bool) -> Datum {
+    compare_scalars(a, b).map_or(Datum::Null, |ordering| Datum::Bool(test(ordering)))
+}
+
+/// Orders two scalars of the same json type, None if they can't be compared
+fn compare_scalars(a: &Datum, b: &Datum) -> Option<Ordering> {
+    match (a, b) {
+        (Datum::Bool(a), Datum::Bool(b)) => Some(a.cmp(b)),
+        (Datum::Float(_), _) | (_, Datum::Float(_)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        _ if a.json_type() == JsonType::String => Some(a.as_str()?.cmp(b.as_str()?)),
+        // Only integers left, compared without going through a float to keep their precision
+        _ => Some(a.as_i64()?.cmp(&b.as_i64()?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::datum::{datum_eq, datum_ge, datum_lt, datum_ne, Datum, Step};
+    use serde_json::json;
+    use std::collections::{BTreeMap, HashSet};
+
+    #[test]
+    fn strings_are_escaped() {
+        let s = Datum::String("say \"hi\"\\\n\t\u{1}é".to_string());
+        assert_eq!(s.to_string(), r#""say \"hi\"\\\n\t\u0001é""#);
+
+        // Keys are escaped too, DEL isn't a control character as far as JSON is concerned
+        let row = Datum::from(json!({"quote\"key": ["back\\slash", "\r\u{7f}\u{1f}"]}));
+        assert_eq!(
+            row.to_json(true),
+            "{\"quote\\\"key\":[\"back\\\\slash\",\"\\r\u{7f}\\u001f\"]}"
+        );
+        let parsed: serde_json::Value = serde_json::from_str(&row.to_json_pretty(2)).unwrap();
+        assert_eq!(Datum::from(parsed), row);
+    }
+
+    #[test]
+    fn whole_floats_keep_their_type() {
+        assert_eq!(Datum::Float(5.0).to_string(), "5.0");
+        assert_eq!(Datum::BigInt(5).to_string(), "5");
+        let parsed: serde_json::Value =
+            serde_json::from_str(&Datum::Float(5.0).to_string()).unwrap();
+        assert!(matches!(Datum::from(parsed), Datum::Float(f) if f == 5.0));
+    }
+
+    #[test]
+    fn sorted_keys_are_sorted_at_every_level() {
+        let row = Datum::from(json!({
+            "zeta": {"b": 1, "a": [{"y": true, "x": null}], "c": {}},
+            "alpha": "first",
+            "mid": [3, {"k2": 2, "k1": 1}],
+        }));
+        let expected = concat!(
+            r#"{"alpha":"first","mid":[3,{"k1":1,"k2":2}],"#,
+            r#""zeta":{"a":[{"x":null,"y":true}],"b":1,"c":{}}}"#
+        );
+        assert_eq!(row.to_json(true), expected);
+    }
+
+    #[test]
+    fn pretty_json_is_indented() {
+        let row = Datum::from(json!({"b": [1, {"d": null}], "a": "x", "e": [], "f": {}}));
+        let expected = r#"{
+  "a": "x",
+  "b": [
+    1,
+    {
+      "d": null
+    }
+  ],
+  "e": [],
+  "f": {}
+}"#;
+        assert_eq!(row.to_json_pretty(2), expected);
+    }
+
+    #[test]
+    fn objects_convert_to_and_from_sorted_maps() {
+        let row = Datum::from(json!({"c": 3, "a": {"z": 1, "y": 2}, "b": null}));
+        let keys: Vec<&str> = row.as_sorted_entries().iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["a", "b", "c"]);
+        assert!(Datum::from(json!([1])).as_sorted_entries().is_empty());
+
+        let map = BTreeMap::try_from(row.clone()).unwrap();
+        assert_eq!(map.keys().collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(map["a"], Datum::from(json!({"y": 2, "z": 1})));
+        assert_eq!(Datum::from(map), row);
+        assert_eq!(BTreeMap::try_from(Datum::TinyInt(1)), Err(Datum::TinyInt(1)));
+    }
+
+    #[test]
+    fn nested_values_are_modified_in_place() {
+        let mut row = Datum::from(json!({"a": {"b": [{"c": 1}, {"c": 2}]}, "d": 3}));
+        let path = [
+            Step::Key("a".to_string()),
+            Step::Key("b".to_string()),
+            Step::Index(1),
+            Step::Key("c".to_string()),
+        ];
+        *row.get_path_mut(&path).unwrap() = Datum::String("x".to_string());
+        assert_eq!(row, Datum::from(json!({"a": {"b": [{"c": 1}, {"c": "x"}]}, "d": 3})));
+        assert_eq!(row.get_path(&path), Some(&Datum::String("x".to_string())));
+
+        // Steps that don't fit the shape of the datum find nothing
+        assert_eq!(row.get_path_mut(&[Step::Key("a".to_string()), Step::Index(0)]), None);
+        let past_the_end = [path[0].clone(), path[1].clone(), Step::Index(2)];
+        assert_eq!(row.get_path_mut(&past_the_end), None);
+    }
+
+    #[test]
+    fn structure_stats_count_leaves_and_depth() {
+        let row = Datum::from(json!({"a": 1, "b": {"c": [1, null, [true]], "d": {}}, "e": "s"}));
+        // a, the three leaves under c and e, with `true` nested under root, b, c and its array
+        assert_eq!(row.structure_stats(), (5, 5));
+        assert_eq!(Datum::from(json!("s")).structure_stats(), (1, 1));
+        assert_eq!(Datum::from(json!([])).structure_stats(), (0, 1));
+        assert_eq!(Datum::from(json!([{}, [[]]])).structure_stats(), (0, 3));
+        assert_eq!(Datum::Missing.structure_stats(), (0, 0));
+    }
+
+    #[test]
+    fn filter_keys_keeps_a_subset() {
+        let row = json!({
+            "id": 1,
+            "name": "a",
+            "user": {"id": 2, "login": "b"},
+            "items": [{"id": 3, "x": 4}],
+        });
+        let keep: HashSet<String> = ["id", "user", "items"].map(String::from).into();
+
+        let mut shallow = Datum::from(row.clone());
+        shallow.filter_keys(&keep, false);
+        let mut expected = row.clone();
+        expected.as_object_mut().unwrap().remove("name");
+        assert_eq!(shallow, Datum::from(expected));
+
+        let mut deep = Datum::from(row);
+        deep.filter_keys(&keep, true);
+        assert_eq!(deep, Datum::from(json!({"id": 1, "user": {"id": 2}, "items": [{"id": 3}]})));
+
+        // Arrays are only descended into when recursive
+        let mut arr = Datum::from(json!([{"id": 1, "x": 2}]));
+        arr.filter_keys(&keep, false);
+        assert_eq!(arr, Datum::from(json!([{"id": 1, "x": 2}])));
+        arr.filter_keys(&keep, true);
+        assert_eq!(arr, Datum::from(json!([{"id": 1}])));
+    }
+
+    #[test]
+    fn comparisons_use_three_valued_logic() {
+        let string = |s: &str| Datum::String(s.to_string());
+        // Anything compared with null or missing is unknown
+        for unknown in [Datum::Null, Datum::Missing] {
+            assert_eq!(datum_eq(&unknown, &Datum::TinyInt(1)), Datum::Null);
+            assert_eq!(datum_ne(&string("a"), &unknown), Datum::Null);
+            assert_eq!(datum_lt(&unknown, &unknown), Datum::Null);
+        }
+
+        // Numbers compare by value whatever their width
+        let ones = [Datum::TinyInt(1), Datum::BigInt(1), Datum::Float(1.0)];
+        for a in &ones {
+            for b in &ones {
+                assert_eq!(datum_eq(a, b), Datum::Bool(true), "{a:?} = {b:?}");
+                assert_eq!(datum_lt(a, b), Datum::Bool(false), "{a:?} < {b:?}");
+            }
+        }
+        assert_eq!(datum_lt(&Datum::TinyInt(1), &Datum::Float(1.5)), Datum::Bool(true));
+        // Integers keep their precision, these are the same as floats
+        let (max, below) = (Datum::BigInt(i64::MAX), Datum::BigInt(i64::MAX - 1));
+        assert_eq!(datum_ge(&max, &below), Datum::Bool(true));
+        assert_eq!(datum_eq(&max, &below), Datum::Bool(false));
+        assert_eq!(datum_eq(&Datum::Float(f64::NAN), &Datum::Float(f64::NAN)), Datum::Null);
+
+        // Strings compare by bytes
+        assert_eq!(datum_lt(&string("B"), &string("a")), Datum::Bool(true));
+        assert_eq!(datum_lt(&string("a"), &string("ab")), Datum::Bool(true));
+        assert_eq!(datum_eq(&string("a"), &Datum::SharedString("a".into())), Datum::Bool(true));
+
+        // Mixed types and nested values can't be compared
+        assert_eq!(datum_eq(&string("1"), &Datum::TinyInt(1)), Datum::Null);
+        assert_eq!(datum_eq(&Datum::from(json!([1])), &Datum::from(json!([1]))), Datum::Null);
+    }
+
+    #[test]
+    fn content_hash_ignores_key_order_and_int_width() {
+        let entries = [("a", Datum::TinyInt(1)), ("b", Datum::from(json!({"c": [1.5, null]})))];
+        let object = |entries: &[(&str, Datum)]| {
+            Datum::Object(entries.iter().map(|(k, v)| (k.to_string(), v.clone())).collect())
+        };
+        let forwards = object(&entries);
+        let backwards = object(&[entries[1].clone(), entries[0].clone()]);
+        assert_eq!(forwards.content_hash(), backwards.content_hash());
+
+        let one = Datum::TinyInt(1).content_hash();
+        for same in [Datum::SmallInt(1), Datum::Int(1), Datum::BigInt(1), Datum::Float(1.0)] {
+            assert_eq!(same.content_hash(), one, "{same:?}");
+        }
+        assert_eq!(Datum::Float(-0.0).content_hash(), Datum::TinyInt(0).content_hash());
+        assert_eq!(
+            Datum::String("s".to_string()).content_hash(),
+            Datum::SharedString("s".into()).content_hash()
+        );
+
+        for different in [Datum::Float(1.5), Datum::String("1".to_string()), Datum::Bool(true)] {
+            assert_ne!(different.content_hash(), one, "{different:?}");
         }
+        let [ascending, descending] = [json!([1, 2]), json!([2, 1])].map(Datum::from);
+        assert_ne!(ascending.content_hash(), descending.content_hash());
+        // Persisted hashes have to stay the same from build to build, these are FNV-1a of the tag
+        // byte for null, and of the integer tag followed by 1 as a little endian i64
+        assert_eq!(Datum::Null.content_hash(), 12638153115695167455);
+        assert_eq!(one, 11544151318564893560);
     }
 }
@@ -1,3 +1,5 @@
+pub mod column_expr;
 pub mod columnar;
 pub mod datum;
 pub mod loader;
+pub mod row_expr;
@@ -1,29 +1,409 @@
+pub mod compression;
+pub mod jsonc;
+pub mod spans;
 use crate::datum::Datum;
+use crate::loader::compression::Compression;
+use crate::loader::jsonc::JsoncReader;
+use serde::de::{SeqAccess, Visitor};
+use serde::Deserializer;
 use serde_json::Value;
+use std::collections::HashSet;
 use std::error::Error;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::Path;
+use std::sync::Arc;
+use std::thread;
 
 /// Loads data from a file into a vec of datum's, used for testing.
+/// None of the loading functions produce `Datum::Missing`, see `Datum::is_loadable`.
+/// `.gz` and `.zst` files are decompressed, see `Compression`.
+///
+/// Integers that don't fit in an `i64` have no exact numeric type to be stored as, so rather than
+/// being rounded to a float they're loaded as a `Datum::String` of their digits. That covers `u64`
+/// values past `i64::MAX`, and with the `arbitrary_precision` feature any longer integer too.
+/// They stay strings from then on, so exporting (eg `Stripe::write_ndjson` or `Stripe::to_csv`)
+/// writes them quoted rather than as numbers.
 pub fn load_json<P: AsRef<Path>>(f: P) -> Result<Vec<Datum>, Box<dyn Error>> {
-    let reader = BufReader::new(File::open(f)?);
+    let compression = Compression::from_path(f.as_ref());
+    load_json_compressed(f, compression)
+}
+
+/// Like `load_json` but with the compression given explicitly rather than going off the extension
+pub fn load_json_compressed<P: AsRef<Path>>(
+    f: P,
+    compression: Compression,
+) -> Result<Vec<Datum>, Box<dyn Error>> {
+    load_json_reader(compression.open(f.as_ref())?)
+}
+
+/// Loads newline delimited json from any reader (stdin, an in memory buffer etc).
+/// If the input is instead a single top level json array, each element is loaded as a row.
+/// Only an array making up the whole input is taken apart like this, an array on the first line
+/// followed by more lines is just the first row. So newline delimited json whose only row is an
+/// array can't be told apart from an array of rows, and is loaded as its elements.
+/// Comments and trailing commas are allowed, lines left blank once comments are removed are skipped.
+pub fn load_json_reader<R: BufRead>(reader: R) -> Result<Vec<Datum>, Box<dyn Error>> {
+    load_reader(reader, None, None)
+}
+
+/// Lines that failed to parse as `(line number, error message)`
+pub type BadLines = Vec<(usize, String)>;
+
+/// Like `load_json` but lines that fail to parse are skipped rather than failing the load, they're
+/// returned alongside the data.
+/// Only per-line errors are tolerated, failing to read the file or a malformed top level array
+/// still return an error.
+pub fn load_json_lossy<P: AsRef<Path>>(f: P) -> Result<(Vec<Datum>, BadLines), Box<dyn Error>> {
+    let mut bad_lines = Vec::new();
+    let reader = Compression::from_path(f.as_ref()).open(f.as_ref())?;
+    let results = load_reader(reader, Some(&mut bad_lines), None)?;
+    Ok((results, bad_lines))
+}
+
+/// Like `load_json` but repeated string values share a single allocation, stored as
+/// `Datum::SharedString`. Worthwhile when values like usernames or statuses repeat across many rows.
+pub fn load_json_interned<P: AsRef<Path>>(f: P) -> Result<Vec<Datum>, Box<dyn Error>> {
+    let reader = Compression::from_path(f.as_ref()).open(f.as_ref())?;
+    load_reader(reader, None, Some(&mut StringPool::new()))
+}
+
+/// Loads newline delimited json or a top level array, if `bad_lines` is given lines that fail to
+/// parse are collected there instead of returning an error. If `pool` is given strings are
+/// interned into it.
+fn load_reader<R: BufRead>(
+    reader: R,
+    bad_lines: Option<&mut BadLines>,
+    pool: Option<&mut StringPool>,
+) -> Result<Vec<Datum>, Box<dyn Error>> {
     let mut results = Vec::new();
+    read_rows(reader, bad_lines, pool, |row| {
+        results.push(row);
+        true
+    })?;
+    Ok(results)
+}
+
+/// Reads rows just as `load_reader` would, handing each to `f` until it returns false.
+/// A top level array making up the whole input is read in full before any of its rows are handed
+/// over.
+pub(crate) fn read_rows<R: BufRead>(
+    reader: R,
+    mut bad_lines: Option<&mut BadLines>,
+    mut pool: Option<&mut StringPool>,
+    mut f: impl FnMut(Datum) -> bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut reader = BufReader::new(JsoncReader::new(reader));
+    let (skipped_lines, next) = skip_whitespace(&mut reader)?;
+    let mut first_line = skipped_lines + 1;
+    if next == Some(b'[') {
+        match read_leading_array(&mut reader, first_line, pool.as_deref_mut())? {
+            LeadingArray::Whole(rows) => {
+                for row in rows {
+                    if !f(row) {
+                        break;
+                    }
+                }
+                return Ok(());
+            }
+            LeadingArray::FirstRow { row, next_line } => {
+                if !f(row) {
+                    return Ok(());
+                }
+                first_line = next_line;
+            }
+        }
+    }
 
-    //let mut c =0;
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value = match serde_json::from_str(&line) {
+            Ok(value) => value,
+            Err(source) => {
+                let err = LoadError { line: first_line + idx, source };
+                match &mut bad_lines {
+                    Some(bad_lines) => {
+                        bad_lines.push((err.line, err.to_string()));
+                        continue;
+                    }
+                    None => return Err(err.into()),
+                }
+            }
+        };
+        if !f(convert_row(value, pool.as_deref_mut())) {
+            break;
+        }
+    }
+    Ok(())
+}
 
-    for line in reader.lines() {
-        let value: Value = serde_json::from_str(&line?)?;
-        results.push(convert_from_value(value));
-        // c += 1;
-        // if c > 100 {
-        //     break;
-        // }
+/// Like `load_json` but the lines are split into chunks which are parsed across all cores.
+/// Rows come back in the same order as the file.
+/// Unlike `load_json` the whole (decompressed) file is read into memory up front and kept until
+/// every row is parsed, so memory use peaks at the size of the text on top of the rows themselves.
+/// Stick to `load_json` for files that don't comfortably fit in memory twice over.
+pub fn load_json_parallel<P: AsRef<Path>>(f: P) -> Result<Vec<Datum>, Box<dyn Error>> {
+    let mut text = String::new();
+    let reader = Compression::from_path(f.as_ref()).open(f.as_ref())?;
+    JsoncReader::new(reader).read_to_string(&mut text)?;
+    let mut rest = text.as_bytes();
+    let mut first_row = None;
+    let (skipped_lines, next) = skip_whitespace(&mut rest)?;
+    let mut first_line = skipped_lines;
+    if next == Some(b'[') {
+        match read_leading_array(&mut rest, first_line + 1, None)? {
+            LeadingArray::Whole(rows) => return Ok(rows),
+            LeadingArray::FirstRow { row, next_line } => {
+                first_row = Some(row);
+                first_line = next_line - 1;
+            }
+        }
     }
+    // Only ascii whitespace and the leading array have been read, so we're on a char boundary
+    let text = &text[text.len() - rest.len()..];
+
+    let lines: Vec<&str> = text.lines().collect();
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = lines.len().div_ceil(threads).max(1);
+    let chunks = thread::scope(|scope| {
+        let handles: Vec<_> = lines
+            .chunks(chunk_size)
+            .enumerate()
+            .map(|(idx, chunk)| {
+                scope.spawn(move || parse_lines(chunk, first_line + idx * chunk_size))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+    Ok(first_row.into_iter().chain(chunks.into_iter().flatten()).collect())
+}
 
+/// Parses a chunk of lines, `first_line` is the 0-based line number of the first line in the chunk.
+fn parse_lines(lines: &[&str], first_line: usize) -> Result<Vec<Datum>, LoadError> {
+    let mut results = Vec::with_capacity(lines.len());
+    for (idx, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value = serde_json::from_str(line).map_err(|source| LoadError {
+            line: first_line + idx + 1,
+            source,
+        })?;
+        results.push(convert_row(value, None));
+    }
+    Ok(results)
+}
+
+/// Error for a line of newline delimited json that failed to parse
+#[derive(Debug)]
+pub struct LoadError {
+    /// 1-based line number within the input
+    pub line: usize,
+    pub source: serde_json::Error,
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Each line is parsed on its own so serde's position is always on line 1, swap it for ours
+        let message = self.source.to_string();
+        let suffix = format!(" at line {} column {}", self.source.line(), self.source.column());
+        let message = message.strip_suffix(&suffix).unwrap_or(&message);
+        write!(f, "{} at line {} column {}", message, self.line, self.source.column())
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Loads a single top level json array, streaming each element into a datum as it's parsed.
+/// Comments and trailing commas are allowed.
+pub fn load_json_array<R: BufRead>(reader: R) -> Result<Vec<Datum>, Box<dyn Error>> {
+    load_array(BufReader::new(JsoncReader::new(reader)), None)
+}
+
+fn load_array<R: BufRead>(
+    reader: R,
+    pool: Option<&mut StringPool>,
+) -> Result<Vec<Datum>, Box<dyn Error>> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let results = deserializer.deserialize_seq(ArrayVisitor { pool })?;
+    deserializer.end()?;
     Ok(results)
 }
 
+struct ArrayVisitor<'a> {
+    pool: Option<&'a mut StringPool>,
+}
+
+impl<'de> Visitor<'de> for ArrayVisitor<'_> {
+    type Value = Vec<Datum>;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("a json array")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut results = Vec::new();
+        while let Some(value) = seq.next_element::<Value>()? {
+            results.push(convert_row(value, self.pool.as_deref_mut()));
+        }
+        Ok(results)
+    }
+}
+
+/// How input opening with an array turned out to be laid out
+enum LeadingArray {
+    /// The array was the whole input, its elements are the rows
+    Whole(Vec<Datum>),
+    /// The array was the first row of newline delimited json, carrying on at the 1-based
+    /// `next_line`
+    FirstRow { row: Datum, next_line: usize },
+}
+
+/// Reads the array the input opens with, `line` being the 1-based line it starts on.
+/// For the input to be newline delimited json the array has to sit on a line of its own, anything
+/// else following the array is an error.
+fn read_leading_array<R: BufRead>(
+    reader: &mut R,
+    line: usize,
+    pool: Option<&mut StringPool>,
+) -> Result<LeadingArray, Box<dyn Error>> {
+    let mut counter = CountNewlines { reader, newlines: 0 };
+    // Stops right after the closing bracket, leaving the rest of the input unread
+    let elements = serde_json::Deserializer::from_reader(&mut counter)
+        .deserialize_seq(ArrayVisitor { pool })
+        .map_err(|source| LoadError { line: line + source.line() - 1, source })?;
+    let array_lines = counter.newlines;
+    match skip_whitespace(counter.reader)? {
+        (_, None) => Ok(LeadingArray::Whole(elements)),
+        (newlines, Some(_)) if array_lines == 0 && newlines > 0 => Ok(LeadingArray::FirstRow {
+            row: Datum::Array(elements),
+            next_line: line + newlines,
+        }),
+        (newlines, Some(_)) => Err(LoadError {
+            line: line + array_lines + newlines,
+            source: serde::de::Error::custom("trailing characters after the top level array"),
+        }
+        .into()),
+    }
+}
+
+/// Counts the newlines read through it
+struct CountNewlines<'a, R> {
+    reader: &'a mut R,
+    newlines: usize,
+}
+
+impl<R: Read> Read for CountNewlines<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        self.newlines += buf[..len].iter().filter(|b| **b == b'\n').count();
+        Ok(len)
+    }
+}
+
+/// Skips over whitespace, returning how many newlines were skipped along with the byte that
+/// follows (left unread), or None if the input ran out
+fn skip_whitespace<R: BufRead>(reader: &mut R) -> io::Result<(usize, Option<u8>)> {
+    let mut newlines = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok((newlines, None));
+        }
+        let pos = buf.iter().position(|b| !b.is_ascii_whitespace());
+        let skipped = &buf[..pos.unwrap_or(buf.len())];
+        newlines += skipped.iter().filter(|b| **b == b'\n').count();
+        match pos {
+            Some(pos) => {
+                let next = buf[pos];
+                reader.consume(pos);
+                return Ok((newlines, Some(next)));
+            }
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// Converts a top level row, json has no way to express a missing value so loaded data never
+/// contains `Datum::Missing`.
+fn convert_row(val: Value, pool: Option<&mut StringPool>) -> Datum {
+    let mut datum = convert_from_value(val);
+    debug_assert!(datum.is_loadable());
+    if let Some(pool) = pool {
+        intern_strings(&mut datum, pool);
+    }
+    datum
+}
+
+/// Every distinct string value seen so far
+type StringPool = HashSet<Arc<str>>;
+
+/// Swaps the strings within a datum for shared copies from the pool
+fn intern_strings(datum: &mut Datum, pool: &mut StringPool) {
+    match datum {
+        Datum::String(s) => {
+            let shared = match pool.get(s.as_str()) {
+                Some(shared) => shared.clone(),
+                None => {
+                    let shared: Arc<str> = Arc::from(std::mem::take(s));
+                    pool.insert(shared.clone());
+                    shared
+                }
+            };
+            *datum = Datum::SharedString(shared);
+        }
+        Datum::Array(arr) => arr.iter_mut().for_each(|datum| intern_strings(datum, pool)),
+        Datum::Object(obj) => obj.values_mut().for_each(|datum| intern_strings(datum, pool)),
+        _ => {}
+    }
+}
+
+/// Converts a serde value using the same rules as the loader, integers are narrowed to the
+/// smallest type that fits.
+impl From<Value> for Datum {
+    fn from(val: Value) -> Self {
+        convert_from_value(val)
+    }
+}
+
+/// Converts back to a serde value, missing values within objects are left out while a missing
+/// array element or top level value becomes null. Non-finite floats also become null.
+impl From<&Datum> for Value {
+    fn from(datum: &Datum) -> Self {
+        match datum {
+            Datum::Null | Datum::Missing => Value::Null,
+            Datum::TinyInt(i) => Value::from(*i),
+            Datum::SmallInt(i) => Value::from(*i),
+            Datum::Int(i) => Value::from(*i),
+            Datum::BigInt(i) => Value::from(*i),
+            Datum::Float(f) => Value::from(*f),
+            Datum::Bool(b) => Value::Bool(*b),
+            Datum::String(s) => Value::String(s.clone()),
+            Datum::SharedString(s) => Value::String(s.to_string()),
+            Datum::Array(arr) => Value::Array(arr.iter().map(Value::from).collect()),
+            Datum::Object(obj) => Value::Object(
+                obj.iter()
+                    .filter(|(_, v)| !v.is_missing())
+                    .map(|(k, v)| (k.clone(), Value::from(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
 /// Converts from serde value into our datum format
 fn convert_from_value(val: Value) -> Datum {
     match val {
@@ -34,6 +414,22 @@ fn convert_from_value(val: Value) -> Datum {
                     return Datum::TinyInt(int as i8);
                 } else if i16::MIN as i64 <= int && int <= i16::MAX as i64 {
                     return Datum::SmallInt(int as i16);
+                } else if i32::MIN as i64 <= int && int <= i32::MAX as i64 {
+                    return Datum::Int(int as i32);
+                }
+                return Datum::BigInt(int);
+            }
+            // Unsigned ids past i64::MAX would be rounded as a float, keep their exact digits
+            if let Some(uint) = n.as_u64() {
+                return Datum::String(uint.to_string());
+            }
+            // The original token tells us if this was written as an integer, if so keep the exact
+            // digits rather than rounding to the nearest float
+            #[cfg(feature = "arbitrary_precision")]
+            {
+                let token = n.to_string();
+                if !token.contains(['.', 'e', 'E']) {
+                    return Datum::String(token);
                 }
             }
             if let Some(f) = n.as_f64() {
@@ -52,3 +448,127 @@ fn convert_from_value(val: Value) -> Datum {
         ),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{load_json_array, load_json_interned, load_json_reader};
+    use crate::columnar::Stripe;
+    use crate::datum::{Datum, Step};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn load(input: &str) -> Vec<Datum> {
+        load_json_reader(input.as_bytes()).unwrap()
+    }
+
+    fn rows(rows: serde_json::Value) -> Vec<Datum> {
+        rows.as_array().unwrap().iter().cloned().map(Datum::from).collect()
+    }
+
+    #[test]
+    fn whole_input_array_is_split_into_rows() {
+        let expected = rows(json!([{"a": 1}, {"a": 2}]));
+        assert_eq!(load("[{\"a\": 1}, {\"a\": 2}]"), expected);
+        assert_eq!(load("\n  [\n  {\"a\": 1},\n  {\"a\": 2},\n]\n\n"), expected);
+    }
+
+    #[test]
+    fn array_rows_of_newline_delimited_json() {
+        assert_eq!(
+            load("[1, 2]\n[3]\n\n{\"a\": [4]}\n"),
+            rows(json!([[1, 2], [3], {"a": [4]}]))
+        );
+        assert_eq!(load("\n[1]\n  [2]"), rows(json!([[1], [2]])));
+    }
+
+    #[test]
+    fn errors_after_a_leading_array_report_their_line() {
+        let err = load_json_reader("[1]\n[2]\n{oops}\n".as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("at line 3"), "{err}");
+
+        let err = load_json_reader("[\n1\n]\n[2]".as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("trailing characters"), "{err}");
+        assert!(err.to_string().contains("at line 4"), "{err}");
+
+        let err = load_json_reader("[1] [2]".as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("trailing characters"), "{err}");
+    }
+
+    #[test]
+    fn integers_past_i64_load_as_strings() {
+        let loaded = load("{\"id\": 18446744073709551615, \"small\": 9223372036854775807}");
+        let mut stripe = Stripe::new();
+        stripe.push_data(&loaded);
+        let Datum::Object(row) = &loaded[0] else { panic!("expected an object") };
+        assert_eq!(row["id"], Datum::String("18446744073709551615".to_string()));
+        assert_eq!(row["small"], Datum::BigInt(i64::MAX));
+
+        let mut out = Vec::new();
+        stripe.write_ndjson(&mut out).unwrap();
+        let written: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(written, json!({"id": "18446744073709551615", "small": 9223372036854775807i64}));
+    }
+
+    #[cfg(feature = "arbitrary_precision")]
+    #[test]
+    fn long_integer_tokens_keep_their_digits() {
+        // 20 digits, past even u64::MAX
+        let loaded = load(
+            "{\"id\": 98765432109876543210, \"neg\": -123456789012345678901, \"f\": 1.5e3}",
+        );
+        let Datum::Object(row) = &loaded[0] else { panic!("expected an object") };
+        assert_eq!(row["id"], Datum::String("98765432109876543210".to_string()));
+        assert_eq!(row["neg"], Datum::String("-123456789012345678901".to_string()));
+        assert_eq!(row["f"], Datum::Float(1500.0));
+    }
+
+    #[test]
+    fn loaded_rows_never_hold_missing() {
+        fn missing_anywhere(datum: &Datum) -> bool {
+            match datum {
+                Datum::Missing => true,
+                Datum::Array(arr) => arr.iter().any(missing_anywhere),
+                Datum::Object(obj) => obj.values().any(missing_anywhere),
+                _ => false,
+            }
+        }
+        let row =
+            r#"{"a": null, "b": {"c": [null, {"d": null}, [], {}]}, "e": [[null], {"f": {}}]}"#;
+        let mut stripe = Stripe::new();
+        stripe.push_data(&load(&format!("{row}\n{{}}\n")));
+        // Reassembling leaves out keys a row doesn't have rather than filling them with Missing
+        let loaded = [
+            load(row),
+            load_json_array(format!("[{row}, [null]]").as_bytes()).unwrap(),
+            stripe.to_data(),
+        ];
+        for rows in &loaded {
+            assert!(!rows.iter().any(missing_anywhere), "{rows:?}");
+            assert!(rows.iter().all(Datum::is_loadable));
+        }
+        assert!(!Datum::Array(vec![Datum::Null, Datum::Missing]).is_loadable());
+    }
+
+    #[test]
+    fn interned_strings_share_an_allocation() {
+        let path =
+            std::env::temp_dir().join(format!("jsonc-interned-{}.ndjson", std::process::id()));
+        let input = "{\"user\": \"ann\", \"tags\": [\"ann\", \"bob\"]}\n{\"user\": \"ann\"}\n";
+        std::fs::write(&path, input).unwrap();
+        let loaded = load_json_interned(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Shared strings still compare equal to the plain strings `load` gives
+        assert_eq!(loaded, load(input));
+        let shared = |row: &Datum, path: &[Step]| match row.get_path(path) {
+            Some(Datum::SharedString(s)) => s.clone(),
+            other => panic!("expected a shared string, got {other:?}"),
+        };
+        let user = [Step::Key("user".to_string())];
+        let tag = |idx| [Step::Key("tags".to_string()), Step::Index(idx)];
+        let ann = shared(&loaded[0], &user);
+        assert!(Arc::ptr_eq(&ann, &shared(&loaded[1], &user)));
+        assert!(Arc::ptr_eq(&ann, &shared(&loaded[0], &tag(0))));
+        assert!(!Arc::ptr_eq(&ann, &shared(&loaded[0], &tag(1))));
+    }
+}
@@ -0,0 +1,41 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// How a file being loaded is compressed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    /// Needs the `gzip` feature
+    Gzip,
+    /// Needs the `zstd` feature
+    Zstd,
+}
+
+impl Compression {
+    /// Guesses the compression from the file extension (`.gz` or `.zst`)
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Opens a file, decompressing it as it's read
+    pub fn open(self, path: &Path) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+        let file = File::open(path)?;
+        Ok(match self {
+            Compression::None => Box::new(BufReader::new(file)),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file))),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+            #[cfg(not(feature = "gzip"))]
+            Compression::Gzip => return Err("loading gzip files needs the `gzip` feature".into()),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => return Err("loading zstd files needs the `zstd` feature".into()),
+        })
+    }
+}
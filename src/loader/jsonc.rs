@@ -0,0 +1,328 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, Error, ErrorKind, Read, Result};
+
+/// Wraps a reader of JSONC (json with `//` and `/* */` comments and trailing commas) producing
+/// plain json that serde can parse.
+/// Comments and trailing commas are blanked out with spaces rather than removed, newlines are
+/// kept, so line and column numbers in any parse errors still line up with the original text.
+/// Stretches with nothing to blank out are copied straight through, so plain json pays little for
+/// the comment handling.
+pub struct JsoncReader<R> {
+    inner: R,
+    state: State,
+    /// Output ready to be read
+    out: VecDeque<u8>,
+    /// A comma and the whitespace following it, held back until we know if it's trailing
+    pending_comma: Option<Vec<u8>>,
+    /// Bytes of input processed so far
+    offset: usize,
+    /// Offset of the last `/` seen outside a string, ie where the current comment started
+    comment_start: usize,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum State {
+    Normal,
+    String,
+    StringEscape,
+    /// Seen a `/` which may start a comment
+    Slash,
+    LineComment,
+    BlockComment,
+    /// Seen a `*` inside a block comment which may end it
+    BlockCommentStar,
+}
+
+impl<R: BufRead> JsoncReader<R> {
+    pub fn new(inner: R) -> Self {
+        JsoncReader {
+            inner,
+            state: State::Normal,
+            out: VecDeque::new(),
+            pending_comma: None,
+            offset: 0,
+            comment_start: 0,
+        }
+    }
+
+    fn process(&mut self, byte: u8) {
+        self.process_byte(byte);
+        self.offset += 1;
+    }
+
+    fn process_byte(&mut self, byte: u8) {
+        match self.state {
+            State::Normal => self.process_normal(byte),
+            State::String => {
+                match byte {
+                    b'\\' => self.state = State::StringEscape,
+                    b'"' => self.state = State::Normal,
+                    _ => {}
+                }
+                self.emit(byte);
+            }
+            State::StringEscape => {
+                self.state = State::String;
+                self.emit(byte);
+            }
+            State::Slash => match byte {
+                b'/' => {
+                    self.state = State::LineComment;
+                    self.emit_blank(b'/');
+                    self.emit_blank(byte);
+                }
+                b'*' => {
+                    self.state = State::BlockComment;
+                    self.emit_blank(b'/');
+                    self.emit_blank(byte);
+                }
+                _ => {
+                    // Not a comment, leave it for the parser to reject
+                    self.state = State::Normal;
+                    self.flush_pending_comma();
+                    self.emit(b'/');
+                    self.process_normal(byte);
+                }
+            },
+            State::LineComment => {
+                if byte == b'\n' {
+                    self.state = State::Normal;
+                }
+                self.emit_blank(byte);
+            }
+            State::BlockComment | State::BlockCommentStar => {
+                self.state = match (self.state, byte) {
+                    (State::BlockCommentStar, b'/') => State::Normal,
+                    (_, b'*') => State::BlockCommentStar,
+                    _ => State::BlockComment,
+                };
+                self.emit_blank(byte);
+            }
+        }
+    }
+
+    fn process_normal(&mut self, byte: u8) {
+        match byte {
+            b'/' => {
+                self.state = State::Slash;
+                self.comment_start = self.offset;
+            }
+            b if b.is_ascii_whitespace() => self.emit(b),
+            b']' | b'}' => {
+                if let Some(pending) = &mut self.pending_comma {
+                    pending[0] = b' ';
+                }
+                self.flush_pending_comma();
+                self.emit(byte);
+            }
+            _ => {
+                self.flush_pending_comma();
+                if byte == b'"' {
+                    self.state = State::String;
+                }
+                if byte == b',' {
+                    self.pending_comma = Some(vec![byte]);
+                } else {
+                    self.emit(byte);
+                }
+            }
+        }
+    }
+
+    fn emit(&mut self, byte: u8) {
+        match &mut self.pending_comma {
+            Some(pending) => pending.push(byte),
+            None => self.out.push_back(byte),
+        }
+    }
+
+    /// Emits the whitespace standing in for a byte of a comment
+    fn emit_blank(&mut self, byte: u8) {
+        self.emit(if byte == b'\n' { b'\n' } else { b' ' });
+    }
+
+    fn flush_pending_comma(&mut self) {
+        if let Some(pending) = self.pending_comma.take() {
+            self.out.extend(pending);
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.state == State::Slash {
+            self.state = State::Normal;
+            self.emit(b'/');
+        }
+        self.flush_pending_comma();
+    }
+
+    /// Offset of a block comment still open at the end of the input
+    fn unterminated_comment(&self) -> Option<usize> {
+        matches!(self.state, State::BlockComment | State::BlockCommentStar)
+            .then_some(self.comment_start)
+    }
+}
+
+/// Blanks out the comments and trailing commas of a whole document at once, as `JsoncReader`
+/// does, so byte offsets into the result line up with `text`.
+/// Errors with the offset of a block comment that's never closed.
+pub(super) fn blank_comments(text: &str) -> std::result::Result<String, usize> {
+    let mut reader = JsoncReader::new(text.as_bytes());
+    for byte in text.bytes() {
+        reader.process(byte);
+    }
+    reader.finish();
+    if let Some(offset) = reader.unterminated_comment() {
+        return Err(offset);
+    }
+    // Only whole comments and ascii commas are blanked, so the rest is left valid utf-8
+    Ok(String::from_utf8(reader.out.into()).expect("blanking keeps the text valid utf-8"))
+}
+
+impl<R: BufRead> Read for JsoncReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        while self.out.is_empty() {
+            let chunk = self.inner.fill_buf()?;
+            if chunk.is_empty() {
+                self.finish();
+                if let Some(offset) = self.unterminated_comment() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("unterminated block comment starting at byte {offset}"),
+                    ));
+                }
+                break;
+            }
+            if self.state == State::Normal && self.pending_comma.is_none() {
+                let plain = plain_prefix(chunk);
+                if plain > 0 {
+                    self.out.extend(&chunk[..plain]);
+                    self.offset += plain;
+                    self.inner.consume(plain);
+                    continue;
+                }
+            }
+            // Otherwise step through a line at most, until we're back to plain json
+            let line_end = chunk
+                .iter()
+                .position(|byte| *byte == b'\n')
+                .map_or(chunk.len(), |idx| idx + 1);
+            let line = chunk[..line_end].to_vec();
+            let mut consumed = 0;
+            for byte in line {
+                consumed += 1;
+                self.process(byte);
+                if self.state == State::Normal && self.pending_comma.is_none() {
+                    break;
+                }
+            }
+            self.inner.consume(consumed);
+        }
+        self.out.read(buf)
+    }
+}
+
+/// Length of the start of `chunk` that has nothing to blank out, so it can be copied straight to
+/// the output instead of going through the state machine a byte at a time.
+/// Stops at the first `/` outside a string, and before a string that's cut off or a comma that
+/// may turn out to be trailing.
+fn plain_prefix(chunk: &[u8]) -> usize {
+    let mut plain = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut after_comma = false;
+    for (idx, &byte) in chunk.iter().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else {
+            match byte {
+                b'/' => break,
+                b']' | b'}' if after_comma => break,
+                b',' => after_comma = true,
+                b'"' => {
+                    in_string = true;
+                    after_comma = false;
+                }
+                b if b.is_ascii_whitespace() => {}
+                _ => after_comma = false,
+            }
+        }
+        if !in_string && !after_comma {
+            plain = idx + 1;
+        }
+    }
+    plain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blank_comments, plain_prefix, JsoncReader};
+    use serde_json::json;
+    use std::io::{BufReader, Read};
+
+    fn strip(input: &str) -> String {
+        let mut out = String::new();
+        JsoncReader::new(input.as_bytes()).read_to_string(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn comments_inside_nested_objects() {
+        let input = r#"{
+  "site": {
+    "url": "http://x", // the home page
+    /* where "links" point, with a // that isn't a comment start */
+    "links": ["http://x/a" /* first */, "/*not a comment*/",],
+    "owner": {"name": "a\"//b", /* inline */ "tags": [],}, // trailing
+  },
+}"#;
+        let out = strip(input);
+        assert_eq!(out.len(), input.len());
+        assert_eq!(out.lines().count(), input.lines().count());
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(
+            value,
+            json!({"site": {
+                "url": "http://x",
+                "links": ["http://x/a", "/*not a comment*/"],
+                "owner": {"name": "a\"//b", "tags": []},
+            }})
+        );
+    }
+
+    #[test]
+    fn unterminated_block_comments_are_an_error() {
+        let mut out = String::new();
+        let err = JsoncReader::new("{\"a\": 1}\n/* oops".as_bytes())
+            .read_to_string(&mut out)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unterminated block comment starting at byte 9");
+        let blanked = blank_comments("[1, /* x */ 2,]").unwrap();
+        assert_eq!((blanked.len(), blanked.replace(' ', "").as_str()), (15, "[1,2]"));
+        assert_eq!(blank_comments("[1] /* x"), Err(4));
+    }
+
+    #[test]
+    fn plain_json_skips_the_state_machine() {
+        assert_eq!(plain_prefix(br#"{"a": "x/y", "b": [1, 2]}"#), 25);
+        assert_eq!(plain_prefix(br#"{"a": 1} // note"#), 9);
+        assert_eq!(plain_prefix(br#"[1, 2, ]"#), 5);
+        assert_eq!(plain_prefix(br#"[1] ["cut \" off"#), 5);
+
+        // However the input is split up the fast path agrees with going byte by byte
+        let input = r#"{"a": "x\"/*y", "b": [1, 2,], // c
+  /* d, */ "e": {"f": "g",},
+}"#;
+        let expected = blank_comments(input).unwrap();
+        for capacity in 1..=input.len() {
+            let mut out = String::new();
+            let reader = BufReader::with_capacity(capacity, input.as_bytes());
+            JsoncReader::new(reader).read_to_string(&mut out).unwrap();
+            assert_eq!(out, expected, "capacity {capacity}");
+        }
+    }
+}
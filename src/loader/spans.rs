@@ -0,0 +1,230 @@
+use crate::datum::{Datum, Step};
+use crate::loader::jsonc::blank_comments;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+/// Byte ranges of every value within a document, keyed by the steps leading to that value.
+/// The root value lives under the empty path.
+pub type SpanTable = BTreeMap<Vec<Step>, Range<usize>>;
+
+/// Error produced when a document can't be parsed, the offset is a byte offset into the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Display for SpanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl Error for SpanError {}
+
+/// Parses a single JSONC document, tracking the source span of every value along the way.
+/// Comments and whitespace are skipped but never included in a value's span, so tooling can
+/// use the spans to map values back onto the original text.
+/// Comments and trailing commas are blanked out as `JsoncReader` does before parsing, and
+/// scalars are parsed with serde, so this accepts exactly what the loaders accept.
+pub fn parse_with_spans(text: &str) -> Result<(Datum, SpanTable), SpanError> {
+    let json = blank_comments(text).map_err(|offset| SpanError {
+        offset,
+        message: "unterminated block comment".to_string(),
+    })?;
+    let mut parser = SpanParser {
+        text: &json,
+        pos: 0,
+        spans: BTreeMap::new(),
+    };
+    let datum = parser.parse_value(&mut Vec::new())?;
+    parser.skip_whitespace();
+    if parser.pos != json.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok((datum, parser.spans))
+}
+
+/// Walks the structure of a document that's had its comments blanked out, the offsets are the
+/// same as in the original text.
+struct SpanParser<'a> {
+    text: &'a str,
+    pos: usize,
+    spans: SpanTable,
+}
+
+impl SpanParser<'_> {
+    fn parse_value(&mut self, path: &mut Vec<Step>) -> Result<Datum, SpanError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let datum = match self.peek() {
+            Some(b'{') => self.parse_object(path)?,
+            Some(b'[') => self.parse_array(path)?,
+            Some(_) => super::convert_from_value(self.parse_scalar()?),
+            None => return Err(self.error("unexpected end of input")),
+        };
+        self.spans.insert(path.clone(), start..self.pos);
+        Ok(datum)
+    }
+
+    fn parse_object(&mut self, path: &mut Vec<Step>) -> Result<Datum, SpanError> {
+        self.pos += 1;
+        let mut obj = HashMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Datum::Object(obj));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected key"));
+            }
+            let key = self.parse_scalar::<String>()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            path.push(Step::Key(key.clone()));
+            let value = self.parse_value(path)?;
+            path.pop();
+            obj.insert(key, value);
+            if !self.parse_separator(b'}')? {
+                return Ok(Datum::Object(obj));
+            }
+        }
+    }
+
+    fn parse_array(&mut self, path: &mut Vec<Step>) -> Result<Datum, SpanError> {
+        self.pos += 1;
+        let mut arr = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Datum::Array(arr));
+        }
+        loop {
+            path.push(Step::Index(arr.len()));
+            let value = self.parse_value(path)?;
+            path.pop();
+            arr.push(value);
+            if !self.parse_separator(b']')? {
+                return Ok(Datum::Array(arr));
+            }
+        }
+    }
+
+    /// Consumes either a comma (returning true) or the closing delimiter (returning false).
+    fn parse_separator(&mut self, close: u8) -> Result<bool, SpanError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b',') => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(b) if b == close => {
+                self.pos += 1;
+                Ok(false)
+            }
+            _ => Err(self.error(&format!("expected ',' or '{}'", close as char))),
+        }
+    }
+
+    /// Lets serde parse a single string, number or literal, leaving `pos` just past it
+    fn parse_scalar<T: DeserializeOwned>(&mut self) -> Result<T, SpanError> {
+        let mut values = serde_json::Deserializer::from_str(&self.text[self.pos..]).into_iter();
+        match values.next() {
+            Some(Ok(value)) => {
+                self.pos += values.byte_offset();
+                Ok(value)
+            }
+            Some(Err(e)) => Err(self.error(&e.to_string())),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), SpanError> {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", b as char)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn error(&self, message: &str) -> SpanError {
+        SpanError {
+            offset: self.pos,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_with_spans;
+    use crate::datum::{Datum, Step};
+
+    #[test]
+    fn values_report_their_byte_spans() {
+        let text =
+            "{\n  // the owner\n  \"user\": {\"name\": \"ann\" /* inline */, \"ids\": [7, 42]},\n}";
+        let (datum, spans) = parse_with_spans(text).unwrap();
+        assert_eq!(spans[&vec![]], 0..text.len());
+
+        let name = vec![Step::Key("user".to_string()), Step::Key("name".to_string())];
+        assert_eq!(&text[spans[&name].clone()], "\"ann\"");
+        let user = vec![Step::Key("user".to_string())];
+        assert_eq!(
+            &text[spans[&user].clone()],
+            "{\"name\": \"ann\" /* inline */, \"ids\": [7, 42]}"
+        );
+        let second_id = vec![
+            Step::Key("user".to_string()),
+            Step::Key("ids".to_string()),
+            Step::Index(1),
+        ];
+        let start = text.find("42").unwrap();
+        assert_eq!(spans[&second_id], start..start + 2);
+        assert_eq!(datum.get_path(&second_id), Some(&Datum::TinyInt(42)));
+    }
+
+    #[test]
+    fn escaped_quotes_stay_inside_their_string() {
+        let text = r#"{"say \"hi\"": "a \"b\" // c", "n": 1}"#;
+        let (datum, spans) = parse_with_spans(text).unwrap();
+        let key = vec![Step::Key("say \"hi\"".to_string())];
+        assert_eq!(datum.get_path(&key), Some(&Datum::String("a \"b\" // c".to_string())));
+        assert_eq!(&text[spans[&key].clone()], r#""a \"b\" // c""#);
+        assert_eq!(&text[spans[&vec![Step::Key("n".to_string())]].clone()], "1");
+    }
+
+    #[test]
+    fn errors_report_their_offset() {
+        let text = "{\"a\": 1 /* never closed";
+        let err = parse_with_spans(text).unwrap_err();
+        assert_eq!(err.offset, text.find("/*").unwrap());
+        assert_eq!(err.message, "unterminated block comment");
+
+        let text = "{\"a\": [1, 2 3]}";
+        let err = parse_with_spans(text).unwrap_err();
+        assert_eq!(err.offset, text.find('3').unwrap());
+        assert_eq!(err.message, "expected ',' or ']'");
+
+        // Numbers follow the json grammar
+        assert_eq!(parse_with_spans("[1, +2]").unwrap_err().offset, 4);
+        assert_eq!(parse_with_spans("[1e]").unwrap_err().offset, 1);
+        assert_eq!(parse_with_spans("[1] x").unwrap_err().offset, 4);
+    }
+}
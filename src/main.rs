@@ -1,6 +1,8 @@
+use jsonc::column_expr::avg_using_column_expr;
 use jsonc::columnar::{ColumnData, PathComponent, Stripe};
 use jsonc::datum::Datum;
-use jsonc::loader::load_json;
+use jsonc::loader::{load_json, load_json_parallel};
+use jsonc::row_expr::{avg_using_row_expr, RowExpr};
 use std::error::Error;
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
@@ -14,14 +16,35 @@ fn main() -> Result<(), Box<dyn Error>> {
     let duration = start.elapsed();
     println!("Loaded {} rows of data in {duration:?}", data.len());
 
+    println!("Loading data in parallel");
+    let start = Instant::now();
+    let parallel_data = load_json_parallel("github_all_columns.ndjson")?;
+    let duration = start.elapsed();
+    println!("Loaded {} rows of data in {duration:?}", parallel_data.len());
+    if parallel_data == data {
+        println!("Parallel load matches the serial load");
+    } else {
+        let first_difference = data
+            .iter()
+            .zip(&parallel_data)
+            .position(|(serial, parallel)| serial != parallel)
+            .unwrap_or(data.len().min(parallel_data.len()));
+        println!("Parallel load differs from the serial load, first at row {first_difference}");
+    }
+    drop(parallel_data);
+
     println!("Converting to columnar");
     let start = Instant::now();
     let mut columnar = Stripe::new();
-    for datum in &data {
-        columnar.push_datum(datum);
+    for batch in data.chunks(10_000) {
+        columnar.push_data(batch);
     }
     let duration = start.elapsed();
-    println!("Columnarised data in {duration:?}");
+    println!(
+        "Columnarised data in {duration:?}, {} rows across {} columns",
+        columnar.len(),
+        columnar.columns().count()
+    );
 
     {
         let writer = BufWriter::new(File::create("json.columns")?);
@@ -41,11 +64,13 @@ fn main() -> Result<(), Box<dyn Error>> {
         "hand rolled row",
         &data,
     );
+    perf_test(average_review_comments_row_expr, "row expr", &data);
     perf_test(
         average_review_comments_hand_rolled_column,
         "hand rolled columnar",
         &columnar,
     );
+    perf_test(average_review_comments_column_expr, "column expr", &columnar);
     //println!("{columnar:?}");
     Ok(())
 }
@@ -65,18 +90,23 @@ fn average_review_comments_hand_rolled_row(data: &[Datum]) -> f64 {
     let mut count = 0_u64;
 
     for datum in data {
-        if let Datum::Object(root) = datum {
-            if let Some(datum) = root.get("review_comments") {
-                if let Some(number) = datum.as_f64() {
-                    sum += number;
-                    count += 1;
-                }
-            }
+        if let Some(number) = datum.get("review_comments").and_then(Datum::as_f64) {
+            sum += number;
+            count += 1;
         }
     }
     sum / count as f64
 }
 
+fn average_review_comments_row_expr(data: &[Datum]) -> f64 {
+    avg_using_row_expr(&RowExpr::Field("review_comments".to_string()), data).unwrap_or(f64::NAN)
+}
+
+fn average_review_comments_column_expr(stripe: &Stripe) -> f64 {
+    let path = vec![PathComponent::Key("review_comments".to_string())];
+    avg_using_column_expr(stripe, &path).unwrap_or(f64::NAN)
+}
+
 fn average_review_comments_hand_rolled_column(stripe: &Stripe) -> f64 {
     let path = vec![PathComponent::Key("review_comments".to_string())];
     let mut sum = 0.0;
@@ -0,0 +1,582 @@
+use crate::datum::{
+    datum_eq, datum_ge, datum_gt, datum_le, datum_lt, datum_ne, Datum, DatumKey, Step,
+};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+const NULL: &Datum = &Datum::Null;
+
+/// An expression evaluated against a single row at a time
+#[derive(Clone, Debug, PartialEq)]
+pub enum RowExpr {
+    /// A top level key of the row, null if the row isn't an object or doesn't have the key
+    Field(String),
+    /// A nested value within the row, see `Datum::get_path`. Null if any step doesn't resolve
+    Path(Vec<Step>),
+    /// A constant value
+    Literal(Datum),
+    /// Compares two values giving a `Datum::Bool`, or null if either side is null or the types
+    /// can't be compared, see `datum_eq` and friends
+    Compare {
+        lhs: Box<RowExpr>,
+        op: CompareOp,
+        rhs: Box<RowExpr>,
+    },
+    /// Arithmetic between two numbers, null if either side isn't a number. Integers stay
+    /// integers unless the result overflows, and division always gives a float (or null when
+    /// dividing by zero).
+    BinaryOp {
+        op: ArithOp,
+        lhs: Box<RowExpr>,
+        rhs: Box<RowExpr>,
+    },
+    /// The elements of an array, when aggregated each element is fed in separately rather than
+    /// the array as a whole. Anything other than an array has no elements.
+    ArrayElements(Box<RowExpr>),
+    /// The length of an array, null for anything else
+    ArrayLen(Box<RowExpr>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl RowExpr {
+    /// Evaluates the expression against a row, returning an owned value
+    pub fn evaluate(&self, row: &Datum) -> Datum {
+        match self {
+            RowExpr::Field(_) | RowExpr::Path(_) => {
+                self.evaluate_ref(row).cloned().unwrap_or(Datum::Null)
+            }
+            RowExpr::Literal(datum) => datum.clone(),
+            RowExpr::Compare { lhs, op, rhs } => {
+                let compare = match op {
+                    CompareOp::Eq => datum_eq,
+                    CompareOp::Ne => datum_ne,
+                    CompareOp::Lt => datum_lt,
+                    CompareOp::Le => datum_le,
+                    CompareOp::Gt => datum_gt,
+                    CompareOp::Ge => datum_ge,
+                };
+                compare(&lhs.evaluate_cow(row), &rhs.evaluate_cow(row))
+            }
+            RowExpr::BinaryOp { op, lhs, rhs } => {
+                let lhs = Number::from_datum(&lhs.evaluate_cow(row));
+                let rhs = Number::from_datum(&rhs.evaluate_cow(row));
+                match (lhs, rhs) {
+                    (Some(lhs), Some(rhs)) => lhs
+                        .arithmetic(*op, rhs)
+                        .map_or(Datum::Null, Number::into_datum),
+                    _ => Datum::Null,
+                }
+            }
+            RowExpr::ArrayElements(_) => self
+                .evaluate_ref(row)
+                .cloned()
+                .unwrap_or(Datum::Array(Vec::new())),
+            RowExpr::ArrayLen(expr) => match &*expr.evaluate_cow(row) {
+                Datum::Array(arr) => Datum::BigInt(arr.len() as i64),
+                _ => Datum::Null,
+            },
+        }
+    }
+
+    /// Like `evaluate` but borrows the value from the row rather than cloning it.
+    /// Returns None for expressions that compute a new value, fall back to `evaluate` for those.
+    pub fn evaluate_ref<'a>(&self, row: &'a Datum) -> Option<&'a Datum> {
+        match self {
+            RowExpr::Field(key) => Some(row.get(key).unwrap_or(NULL)),
+            RowExpr::Path(path) => Some(row.get_path(path).unwrap_or(NULL)),
+            RowExpr::ArrayElements(expr) => expr
+                .evaluate_ref(row)
+                .filter(|datum| matches!(datum, Datum::Array(_))),
+            RowExpr::Literal(_)
+            | RowExpr::Compare { .. }
+            | RowExpr::BinaryOp { .. }
+            | RowExpr::ArrayLen(_) => None,
+        }
+    }
+
+    /// Borrows the value from the row where possible, otherwise evaluates it
+    fn evaluate_cow<'a>(&self, row: &'a Datum) -> Cow<'a, Datum> {
+        match self.evaluate_ref(row) {
+            Some(datum) => Cow::Borrowed(datum),
+            None => Cow::Owned(self.evaluate(row)),
+        }
+    }
+
+    /// Feeds the value of the expression for every row into an aggregate.
+    /// If there's a filter only rows where it's true are fed in, like a sql `WHERE` rows where
+    /// it's false or null are skipped.
+    pub fn evaluate_agg(
+        &self,
+        data: &[Datum],
+        filter: Option<&RowExpr>,
+        agg: &mut dyn RowAggFunction,
+    ) {
+        for row in data {
+            if matches_filter(filter, row) {
+                self.feed(row, agg);
+            }
+        }
+    }
+
+    /// Feeds the value for a row into an aggregate, or each element for `ArrayElements`
+    fn feed(&self, row: &Datum, agg: &mut dyn RowAggFunction) {
+        match (self, &*self.evaluate_cow(row)) {
+            (RowExpr::ArrayElements(_), Datum::Array(arr)) => {
+                arr.iter().for_each(|datum| agg.process(datum))
+            }
+            (_, datum) => agg.process(datum),
+        }
+    }
+}
+
+/// Whether a row should be aggregated, ie there's no filter or the filter is true for the row
+fn matches_filter(filter: Option<&RowExpr>, row: &Datum) -> bool {
+    filter.is_none_or(|filter| filter.evaluate_cow(row).as_bool() == Some(true))
+}
+
+/// Aggregates an expression separately for each distinct value of a key expression, like a sql
+/// `GROUP BY`. Keys are compared by json value (see `DatumKey`), and rows without the key are
+/// grouped under null.
+pub struct GroupBy {
+    key: RowExpr,
+    value: RowExpr,
+    new_agg: fn() -> Box<dyn RowAggFunction>,
+    /// Index of each key's group, the groups are kept in the order they're first seen
+    group_indexes: HashMap<DatumKey, usize>,
+    groups: Vec<(Datum, Box<dyn RowAggFunction>)>,
+}
+
+impl GroupBy {
+    /// `new_agg` creates the aggregate for each group as it's first seen
+    pub fn new(key: RowExpr, value: RowExpr, new_agg: fn() -> Box<dyn RowAggFunction>) -> Self {
+        GroupBy {
+            key,
+            value,
+            new_agg,
+            group_indexes: HashMap::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    /// Feeds each row into the aggregate for its key, skipping rows the filter doesn't match as
+    /// in `RowExpr::evaluate_agg`. Can be called repeatedly to group across batches of rows.
+    pub fn process(&mut self, data: &[Datum], filter: Option<&RowExpr>) {
+        for row in data {
+            if !matches_filter(filter, row) {
+                continue;
+            }
+            let key = DatumKey(self.key.evaluate(row));
+            let idx = match self.group_indexes.get(&key) {
+                Some(idx) => *idx,
+                None => {
+                    self.groups.push((key.0.clone(), (self.new_agg)()));
+                    self.group_indexes.insert(key, self.groups.len() - 1);
+                    self.groups.len() - 1
+                }
+            };
+            self.value.feed(row, self.groups[idx].1.as_mut());
+        }
+    }
+
+    /// Each key along with the result of its aggregate, in the order the keys were first seen
+    pub fn results(&self) -> Vec<(Datum, Datum)> {
+        self.groups
+            .iter()
+            .map(|(key, agg)| (key.clone(), agg.result()))
+            .collect()
+    }
+}
+
+/// An aggregate built up a value at a time
+pub trait RowAggFunction {
+    fn process(&mut self, datum: &Datum);
+    fn result(&self) -> Datum;
+}
+
+/// Averages the numbers it's fed, anything else is ignored, null if there were no numbers
+#[derive(Clone, Debug, Default)]
+pub struct AverageFunction {
+    sum: f64,
+    count: u64,
+}
+
+impl AverageFunction {
+    pub(crate) fn process_number(&mut self, number: Number) {
+        self.sum += number.as_f64();
+        self.count += 1;
+    }
+}
+
+impl RowAggFunction for AverageFunction {
+    fn process(&mut self, datum: &Datum) {
+        if let Some(number) = Number::from_datum(datum) {
+            self.process_number(number);
+        }
+    }
+
+    fn result(&self) -> Datum {
+        match self.count {
+            0 => Datum::Null,
+            count => Datum::Float(self.sum / count as f64),
+        }
+    }
+}
+
+/// Sums the numbers it's fed, anything else is ignored.
+/// Stays an integer until a float is seen or the sum overflows, null if there were no numbers.
+#[derive(Clone, Debug, Default)]
+pub struct SumFunction {
+    sum: Option<Number>,
+}
+
+impl SumFunction {
+    pub(crate) fn process_number(&mut self, number: Number) {
+        self.sum = Some(match self.sum {
+            None => number,
+            Some(sum) => sum.add(number),
+        });
+    }
+}
+
+impl RowAggFunction for SumFunction {
+    fn process(&mut self, datum: &Datum) {
+        if let Some(number) = Number::from_datum(datum) {
+            self.process_number(number);
+        }
+    }
+
+    fn result(&self) -> Datum {
+        self.sum.map_or(Datum::Null, Number::into_datum)
+    }
+}
+
+/// The smallest number it's fed, anything else is ignored, null if there were no numbers
+#[derive(Clone, Debug, Default)]
+pub struct MinFunction {
+    min: Option<Number>,
+}
+
+impl MinFunction {
+    pub(crate) fn process_number(&mut self, number: Number) {
+        if self.min.is_none_or(|min| number.cmp(min) == Ordering::Less) {
+            self.min = Some(number);
+        }
+    }
+}
+
+impl RowAggFunction for MinFunction {
+    fn process(&mut self, datum: &Datum) {
+        if let Some(number) = Number::from_datum(datum) {
+            self.process_number(number);
+        }
+    }
+
+    fn result(&self) -> Datum {
+        self.min.map_or(Datum::Null, Number::into_datum)
+    }
+}
+
+/// The largest number it's fed, anything else is ignored, null if there were no numbers
+#[derive(Clone, Debug, Default)]
+pub struct MaxFunction {
+    max: Option<Number>,
+}
+
+impl MaxFunction {
+    pub(crate) fn process_number(&mut self, number: Number) {
+        if self
+            .max
+            .is_none_or(|max| number.cmp(max) == Ordering::Greater)
+        {
+            self.max = Some(number);
+        }
+    }
+}
+
+impl RowAggFunction for MaxFunction {
+    fn process(&mut self, datum: &Datum) {
+        if let Some(number) = Number::from_datum(datum) {
+            self.process_number(number);
+        }
+    }
+
+    fn result(&self) -> Datum {
+        self.max.map_or(Datum::Null, Number::into_datum)
+    }
+}
+
+/// Counts the values it's fed, either all of them (like `count(*)`) or just the non-null ones
+#[derive(Clone, Debug, Default)]
+pub struct CountFunction {
+    count: u64,
+    include_nulls: bool,
+}
+
+impl CountFunction {
+    /// Counts every row, nulls included
+    pub fn rows() -> Self {
+        CountFunction {
+            count: 0,
+            include_nulls: true,
+        }
+    }
+
+    /// Counts the rows where the value isn't null or missing
+    pub fn non_null() -> Self {
+        CountFunction::default()
+    }
+
+    /// Counts several values at once, that have already been checked against `include_nulls`
+    pub(crate) fn add(&mut self, count: u64) {
+        self.count += count;
+    }
+}
+
+impl RowAggFunction for CountFunction {
+    fn process(&mut self, datum: &Datum) {
+        if self.include_nulls || !(datum.is_null() || datum.is_missing()) {
+            self.count += 1;
+        }
+    }
+
+    fn result(&self) -> Datum {
+        Datum::BigInt(self.count as i64)
+    }
+}
+
+/// Looks up an aggregate by name, one of `avg`, `sum`, `min`, `max`, `count` (non-null values)
+/// or `count_rows`
+pub fn agg_function(name: &str) -> Option<Box<dyn RowAggFunction>> {
+    Some(match name {
+        "avg" => Box::new(AverageFunction::default()),
+        "sum" => Box::new(SumFunction::default()),
+        "min" => Box::new(MinFunction::default()),
+        "max" => Box::new(MaxFunction::default()),
+        "count" => Box::new(CountFunction::non_null()),
+        "count_rows" => Box::new(CountFunction::rows()),
+        _ => return None,
+    })
+}
+
+/// A number fed into an aggregate, integers of every width are kept exact
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    pub(crate) fn from_datum(datum: &Datum) -> Option<Number> {
+        match datum {
+            Datum::Float(f) => Some(Number::Float(*f)),
+            _ => datum.as_i64().map(Number::Int),
+        }
+    }
+
+    pub(crate) fn into_datum(self) -> Datum {
+        match self {
+            Number::Int(i) => Datum::BigInt(i),
+            Number::Float(f) => Datum::Float(f),
+        }
+    }
+
+    pub(crate) fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+
+    pub(crate) fn add(self, other: Number) -> Number {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => match a.checked_add(b) {
+                Some(sum) => Number::Int(sum),
+                None => Number::Float(a as f64 + b as f64),
+            },
+            _ => Number::Float(self.as_f64() + other.as_f64()),
+        }
+    }
+
+    /// See `RowExpr::BinaryOp`, None when dividing by zero
+    fn arithmetic(self, op: ArithOp, other: Number) -> Option<Number> {
+        let (a, b) = (self.as_f64(), other.as_f64());
+        Some(match (op, self, other) {
+            (ArithOp::Add, _, _) => self.add(other),
+            (ArithOp::Sub, Number::Int(x), Number::Int(y)) => {
+                x.checked_sub(y).map_or(Number::Float(a - b), Number::Int)
+            }
+            (ArithOp::Sub, _, _) => Number::Float(a - b),
+            (ArithOp::Mul, Number::Int(x), Number::Int(y)) => {
+                x.checked_mul(y).map_or(Number::Float(a * b), Number::Int)
+            }
+            (ArithOp::Mul, _, _) => Number::Float(a * b),
+            (ArithOp::Div, _, _) if b == 0.0 => return None,
+            (ArithOp::Div, _, _) => Number::Float(a / b),
+        })
+    }
+
+    /// Integers compare exactly, anything involving a float compares as floats with NaN last
+    fn cmp(self, other: Number) -> Ordering {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => a.cmp(&b),
+            _ => self.as_f64().total_cmp(&other.as_f64()),
+        }
+    }
+}
+
+/// Averages an expression over the rows, None if it wasn't a number for any row
+pub fn avg_using_row_expr(expr: &RowExpr, data: &[Datum]) -> Option<f64> {
+    let mut agg = AverageFunction::default();
+    expr.evaluate_agg(data, None, &mut agg);
+    agg.result().as_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        avg_using_row_expr, ArithOp, AverageFunction, CountFunction, RowAggFunction, RowExpr,
+        SumFunction,
+    };
+    use crate::datum::{Datum, Step};
+    use serde_json::json;
+
+    fn rows(rows: serde_json::Value) -> Vec<Datum> {
+        rows.as_array().unwrap().iter().cloned().map(Datum::from).collect()
+    }
+
+    #[test]
+    fn average_of_small_integers() {
+        let data = rows(json!([{"n": 1}, {"n": 2}, {"n": 300}, {"n": "x"}, {}]));
+        assert!(matches!(data[0].get("n"), Some(Datum::TinyInt(1))));
+        assert!(matches!(data[2].get("n"), Some(Datum::SmallInt(300))));
+
+        let mut agg = AverageFunction::default();
+        RowExpr::Field("n".to_string()).evaluate_agg(&data, None, &mut agg);
+        assert_eq!(agg.result(), Datum::Float(101.0));
+        assert_eq!(avg_using_row_expr(&RowExpr::Field("n".to_string()), &data), Some(101.0));
+    }
+
+    #[test]
+    fn average_of_nothing_is_null() {
+        assert_eq!(AverageFunction::default().result(), Datum::Null);
+        let n = RowExpr::Field("n".to_string());
+        assert_eq!(avg_using_row_expr(&n, &[]), None);
+        assert_eq!(avg_using_row_expr(&n, &rows(json!([{"n": "x"}, {"n": null}, {}]))), None);
+    }
+
+    #[test]
+    fn average_of_a_per_row_ratio() {
+        let data = rows(json!([
+            {"additions": 3, "deletions": 1},
+            {"additions": 1, "deletions": 2},
+            {"additions": 5, "deletions": 0},
+            {"additions": "x", "deletions": 1},
+            {"additions": 2000, "deletions": 2000},
+        ]));
+        let field = |key: &str| Box::new(RowExpr::Field(key.to_string()));
+        let op = |op| RowExpr::BinaryOp { op, lhs: field("additions"), rhs: field("deletions") };
+        assert_eq!(op(ArithOp::Sub).evaluate(&data[0]), Datum::BigInt(2));
+        assert_eq!(op(ArithOp::Div).evaluate(&data[2]), Datum::Null);
+        assert_eq!(op(ArithOp::Div).evaluate(&data[3]), Datum::Null);
+        // Dividing by zero and the non-numeric row are left out of the average
+        assert_eq!(avg_using_row_expr(&op(ArithOp::Div), &data), Some((3.0 + 0.5 + 1.0) / 3.0));
+    }
+
+    #[test]
+    fn array_elements_and_lengths() {
+        let data = rows(json!([
+            {"sizes": [1, 2, 3]},
+            {"sizes": []},
+            {"sizes": 10},
+            {"sizes": null},
+            {},
+            {"sizes": [4]},
+        ]));
+        let sizes = || Box::new(RowExpr::Field("sizes".to_string()));
+        let elements = RowExpr::ArrayElements(sizes());
+
+        // Missing and non-array values have no elements
+        let mut count = CountFunction::rows();
+        elements.evaluate_agg(&data, None, &mut count);
+        assert_eq!(count.result(), Datum::BigInt(4));
+        let mut sum = SumFunction::default();
+        elements.evaluate_agg(&data, None, &mut sum);
+        assert_eq!(sum.result(), Datum::BigInt(10));
+        assert_eq!(elements.evaluate(&data[2]), Datum::Array(Vec::new()));
+        assert_eq!(elements.evaluate(&data[4]), Datum::Array(Vec::new()));
+
+        let len = RowExpr::ArrayLen(sizes());
+        let lens: Vec<Option<i64>> = data.iter().map(|row| len.evaluate(row).as_i64()).collect();
+        assert_eq!(lens, [Some(3), Some(0), None, None, None, Some(1)]);
+        assert_eq!(avg_using_row_expr(&len, &data), Some(4.0 / 3.0));
+    }
+
+    #[test]
+    fn evaluate_ref_borrows_from_the_row() {
+        let row = Datum::from(json!({"name": "jsonc", "repo": {"tags": ["a", "b"]}}));
+        let field = RowExpr::Field("name".to_string());
+        let path = RowExpr::Path(vec![
+            Step::Key("repo".to_string()),
+            Step::Key("tags".to_string()),
+            Step::Index(1),
+        ]);
+
+        let name = field.evaluate_ref(&row).unwrap();
+        assert!(std::ptr::eq(name, row.get("name").unwrap()));
+        assert_eq!(*name, field.evaluate(&row));
+        let tag = path.evaluate_ref(&row).unwrap();
+        let Some(Datum::Array(tags)) = row.get("repo").and_then(|repo| repo.get("tags")) else {
+            panic!("tags should be an array");
+        };
+        assert!(std::ptr::eq(tag, &tags[1]));
+        assert_eq!(*tag, path.evaluate(&row));
+
+        let missing = RowExpr::Path(vec![Step::Key("repo".to_string()), Step::Index(0)]);
+        assert_eq!(RowExpr::Field("x".to_string()).evaluate_ref(&row), Some(&Datum::Null));
+        assert_eq!(missing.evaluate_ref(&row), Some(&Datum::Null));
+        assert_eq!(missing.evaluate(&row), Datum::Null);
+
+        let literal = RowExpr::Literal(Datum::Int(7));
+        assert_eq!(literal.evaluate_ref(&row), None);
+        assert_eq!(literal.evaluate(&row), Datum::Int(7));
+    }
+
+    #[test]
+    fn average_of_a_nested_field() {
+        let data = rows(json!([
+            {"repo": {"stars": 10}},
+            {"repo": {"stars": 2.5}},
+            {"repo": {"forks": 3}},
+            {"repo": "jsonc"},
+            {"stars": 100},
+            {"repo": {"stars": 1000}},
+        ]));
+        let stars = RowExpr::Path(vec![
+            Step::Key("repo".to_string()),
+            Step::Key("stars".to_string()),
+        ]);
+
+        let values: Vec<Datum> = data.iter().map(|row| stars.evaluate(row)).collect();
+        assert_eq!(values[0], Datum::TinyInt(10));
+        assert_eq!(values[2..5], [Datum::Null, Datum::Null, Datum::Null]);
+        assert_eq!(avg_using_row_expr(&stars, &data), Some(1012.5 / 3.0));
+    }
+}